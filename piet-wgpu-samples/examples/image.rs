@@ -2,11 +2,13 @@ use piet_wgpu::{kurbo::Rect, RenderContext, WgpuImage};
 use piet_wgpu_samples::render;
 
 fn main() {
-    render(|renderer| {
-        let image = WgpuImage::from_bytes(include_bytes!("../resources/img/happy-tree.png"));
-        let darth_vader = WgpuImage::from_bytes(include_bytes!("../resources/img/darth_vader.png"));
-        let fire = WgpuImage::from_bytes(include_bytes!("../resources/img/fire.png"));
+    // decoded once, so the images are only uploaded on the first frame
+    let image = WgpuImage::from_bytes(include_bytes!("../resources/img/happy-tree.png")).unwrap();
+    let _darth_vader =
+        WgpuImage::from_bytes(include_bytes!("../resources/img/darth_vader.png")).unwrap();
+    let fire = WgpuImage::from_bytes(include_bytes!("../resources/img/fire.png")).unwrap();
 
+    render(move |renderer| {
         renderer.draw_image(
             &image,
             Rect::new(0.0, 0.0, 200.0, 200.0),
@@ -18,8 +18,6 @@ pub fn render<FN: FnMut(&mut PietWgpu<WgpuImmediateRenderer>) + Sized + 'static>
 
     let (window, event_loop, mut piet_wgpu) = create_window();
 
-    fun(&mut piet_wgpu);
-
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Wait;
         match event {
@@ -31,11 +29,11 @@ pub fn render<FN: FnMut(&mut PietWgpu<WgpuImmediateRenderer>) + Sized + 'static>
                     } => {
                         piet_wgpu.set_scale(scale_factor);
                         piet_wgpu.set_size(new_inner_size.width, new_inner_size.height);
-                        piet_wgpu.finish().unwrap();
+                        window.request_redraw();
                     }
                     WindowEvent::Resized(new_size) => {
                         piet_wgpu.set_size(new_size.width, new_size.height);
-                        piet_wgpu.finish().unwrap();
+                        window.request_redraw();
                     }
                     WindowEvent::CloseRequested
                     | WindowEvent::KeyboardInput {
@@ -51,6 +49,9 @@ pub fn render<FN: FnMut(&mut PietWgpu<WgpuImmediateRenderer>) + Sized + 'static>
                 }
             }
             winit::event::Event::RedrawRequested(window_id) if window.id() == window_id => {
+                // frames don't keep what was drawn before, so the whole scene is drawn again
+                piet_wgpu.begin_frame();
+                fun(&mut piet_wgpu);
                 piet_wgpu.finish().unwrap();
             }
             winit::event::Event::MainEventsCleared => {}
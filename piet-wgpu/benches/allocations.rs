@@ -0,0 +1,49 @@
+//! Renders a few frames headless and prints how many gpu buffers each frame allocated.
+//!
+//! Once the vertex, index and primitive buffers have grown to fit the scene and the staging belt
+//! has enough chunks in rotation, frames shouldn't allocate anymore.
+
+use std::time::Instant;
+
+use piet_wgpu::{immediate::WgpuImmediateRenderer, Color, Config, PietWgpu, Rect, RenderContext};
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 600;
+const FRAMES: usize = 20;
+const RECTS: usize = 5_000;
+
+fn main() {
+    let renderer = match WgpuImmediateRenderer::headless(WIDTH, HEIGHT, 1.0, Config::default()) {
+        Ok(renderer) => renderer,
+        Err(error) => {
+            eprintln!("skipping allocation benchmark: {error}");
+            return;
+        }
+    };
+
+    let mut piet_wgpu = PietWgpu::from_renderer(renderer, WIDTH, HEIGHT, 1.0);
+    let brush = piet_wgpu.solid_brush(Color::rgb8(0x20, 0x80, 0xc0));
+
+    for frame in 0..FRAMES {
+        let allocations = piet_wgpu.renderer.gpu_allocations();
+        let start = Instant::now();
+
+        piet_wgpu.begin_frame();
+        piet_wgpu.clear(None, Color::WHITE);
+
+        for i in 0..RECTS {
+            let x = (i % 100) as f64 * 8.0;
+            let y = (i / 100) as f64 * 8.0;
+
+            piet_wgpu.fill(Rect::new(x, y, x + 6.0, y + 6.0), &brush);
+        }
+
+        piet_wgpu.finish().expect("frame failed");
+
+        println!(
+            "frame {frame:>2}: {} allocations, {:?}",
+            piet_wgpu.renderer.gpu_allocations() - allocations,
+            start.elapsed()
+        );
+    }
+}
@@ -0,0 +1,139 @@
+// padded to 16 bytes on the Rust side
+struct Layer {
+    opacity: f32,
+    // mode fs_blend blends with, see blend()
+    blend_mode: u32,
+    // the root layer is encoded for targets storing colors as written
+    encode_srgb: u32,
+};
+
+@group(0) @binding(0) var<uniform> layer: Layer;
+@group(0) @binding(1) var t_layer: texture_2d<f32>;
+
+// copy of what the layer is composited onto, for fs_blend
+@group(1) @binding(0) var t_backdrop: texture_2d<f32>;
+
+// one triangle covering the whole target, clipped by the scissor rect
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// layers are drawn on transparent, so their colors are premultiplied by alpha already
+fn layer_color(position: vec4<f32>) -> vec4<f32> {
+    // layers are as large as the target, pixels map one to one
+    return textureLoad(t_layer, vec2<i32>(position.xy), 0) * layer.opacity;
+}
+
+fn unpremultiply(color: vec4<f32>) -> vec3<f32> {
+    if (color.a == 0.0) {
+        return vec3<f32>(0.0);
+    }
+    return color.rgb / color.a;
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let lower = color * 12.92;
+    let higher = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(higher, lower, color <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let color = layer_color(position);
+    if (layer.encode_srgb == 0u) {
+        return color;
+    }
+    return vec4<f32>(linear_to_srgb(unpremultiply(color)) * color.a, color.a);
+}
+
+// the blend modes follow the W3C compositing and blending spec
+fn hard_light(backdrop: vec3<f32>, source: vec3<f32>) -> vec3<f32> {
+    let multiply = backdrop * 2.0 * source;
+    let screen = backdrop + (2.0 * source - 1.0) - backdrop * (2.0 * source - 1.0);
+    return select(screen, multiply, source <= vec3<f32>(0.5));
+}
+
+fn color_dodge(backdrop: vec3<f32>, source: vec3<f32>) -> vec3<f32> {
+    let dodged = min(vec3<f32>(1.0), backdrop / max(1.0 - source, vec3<f32>(1e-6)));
+    let result = select(dodged, vec3<f32>(1.0), source >= vec3<f32>(1.0));
+    return select(result, vec3<f32>(0.0), backdrop <= vec3<f32>(0.0));
+}
+
+fn lum(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.3, 0.59, 0.11));
+}
+
+fn clip_color(color: vec3<f32>) -> vec3<f32> {
+    let l = lum(color);
+    let n = min(color.r, min(color.g, color.b));
+    let x = max(color.r, max(color.g, color.b));
+
+    var clipped = color;
+    if (n < 0.0) {
+        clipped = l + (clipped - l) * l / (l - n);
+    }
+    if (x > 1.0) {
+        clipped = l + (clipped - l) * (1.0 - l) / (x - l);
+    }
+    return clipped;
+}
+
+fn set_lum(color: vec3<f32>, l: f32) -> vec3<f32> {
+    return clip_color(color + (l - lum(color)));
+}
+
+fn sat(color: vec3<f32>) -> f32 {
+    return max(color.r, max(color.g, color.b)) - min(color.r, min(color.g, color.b));
+}
+
+// the largest channel becomes s and the smallest 0, the middle one keeps its place between
+fn set_sat(color: vec3<f32>, s: f32) -> vec3<f32> {
+    let c_min = min(color.r, min(color.g, color.b));
+    let c_max = max(color.r, max(color.g, color.b));
+
+    if (c_max > c_min) {
+        return (color - c_min) * s / (c_max - c_min);
+    }
+    return vec3<f32>(0.0);
+}
+
+fn blend(backdrop: vec3<f32>, source: vec3<f32>) -> vec3<f32> {
+    // numbered by BlendMode::shader_mode
+    switch (layer.blend_mode) {
+        // multiply
+        case 1u: { return backdrop * source; }
+        // overlay
+        case 2u: { return hard_light(source, backdrop); }
+        // darken
+        case 3u: { return min(backdrop, source); }
+        // lighten
+        case 4u: { return max(backdrop, source); }
+        // color dodge
+        case 5u: { return color_dodge(backdrop, source); }
+        // difference
+        case 6u: { return abs(backdrop - source); }
+        // hue
+        case 7u: { return set_lum(set_sat(source, sat(backdrop)), lum(backdrop)); }
+        // saturation
+        case 8u: { return set_lum(set_sat(backdrop, sat(source)), lum(backdrop)); }
+        // color
+        case 9u: { return set_lum(source, lum(backdrop)); }
+        // luminosity
+        case 10u: { return set_lum(backdrop, lum(source)); }
+        default: { return source; }
+    }
+}
+
+// composites the layer onto a copy of the backdrop, the result replaces the backdrop
+@fragment
+fn fs_blend(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let source = layer_color(position);
+    let backdrop = textureLoad(t_backdrop, vec2<i32>(position.xy), 0);
+    let blended = blend(unpremultiply(backdrop), unpremultiply(source));
+
+    let rgb = source.rgb * (1.0 - backdrop.a) + backdrop.rgb * (1.0 - source.a)
+        + source.a * backdrop.a * blended;
+    return vec4<f32>(rgb, source.a + backdrop.a - source.a * backdrop.a);
+}
@@ -0,0 +1,102 @@
+// padded to 128 bytes like on the Rust side
+struct Filter {
+    // applied to unpremultiplied colors
+    color_matrix: mat4x4<f32>,
+    offset: vec4<f32>,
+    // of drop shadows, linear and not premultiplied
+    color: vec4<f32>,
+    // one pixel along the blur's axis
+    direction: vec2<i32>,
+    // the source is read this many pixels back
+    shift: vec2<i32>,
+    sigma: f32,
+    taps: i32,
+    // layers hold sRGB encoded colors, filters work on linear ones
+    decode_srgb: u32,
+};
+
+@group(0) @binding(0) var<uniform> params: Filter;
+@group(0) @binding(1) var t_source: texture_2d<f32>;
+// the layer before the filter, for drop shadows
+@group(0) @binding(2) var t_original: texture_2d<f32>;
+
+// one triangle covering the whole texture
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let lower = color / 12.92;
+    let higher = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(higher, lower, color <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let lower = color * 12.92;
+    let higher = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(higher, lower, color <= vec3<f32>(0.0031308));
+}
+
+// layers are premultiplied after encoding, so the encoding is undone on unpremultiplied colors
+fn decode(color: vec4<f32>) -> vec4<f32> {
+    if (params.decode_srgb == 0u || color.a <= 0.0) {
+        return color;
+    }
+    return vec4<f32>(srgb_to_linear(color.rgb / color.a) * color.a, color.a);
+}
+
+fn encode(color: vec4<f32>) -> vec4<f32> {
+    if (params.decode_srgb == 0u || color.a <= 0.0) {
+        return color;
+    }
+    return vec4<f32>(linear_to_srgb(color.rgb / color.a) * color.a, color.a);
+}
+
+// layers are transparent outside of the texture
+fn load_source(position: vec2<i32>) -> vec4<f32> {
+    let size = textureDimensions(t_source);
+    if (any(position < vec2<i32>(0)) || any(position >= size)) {
+        return vec4<f32>(0.0);
+    }
+    return decode(textureLoad(t_source, position, 0));
+}
+
+// one axis of a gaussian blur, the weights are normalized so the edges keep their brightness
+@fragment
+fn fs_blur(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let center = vec2<i32>(position.xy) - params.shift;
+
+    var sum = vec4<f32>(0.0);
+    var weights = 0.0;
+    for (var i: i32 = -params.taps; i <= params.taps; i = i + 1) {
+        let x = f32(i);
+        let weight = exp(-x * x / (2.0 * params.sigma * params.sigma));
+        sum = sum + load_source(center + params.direction * i) * weight;
+        weights = weights + weight;
+    }
+    return encode(sum / weights);
+}
+
+// the blurred alpha of the source colored, below the original layer
+@fragment
+fn fs_drop_shadow(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let coverage = params.color.a * load_source(vec2<i32>(position.xy)).a;
+    let original = decode(textureLoad(t_original, vec2<i32>(position.xy), 0));
+
+    let shadow = vec4<f32>(params.color.rgb * coverage, coverage);
+    return encode(original + shadow * (1.0 - original.a));
+}
+
+@fragment
+fn fs_color_matrix(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let color = load_source(vec2<i32>(position.xy));
+    var rgb = vec3<f32>(0.0);
+    if (color.a > 0.0) {
+        rgb = color.rgb / color.a;
+    }
+
+    let filtered = clamp(params.color_matrix * vec4<f32>(rgb, color.a) + params.offset, vec4<f32>(0.0), vec4<f32>(1.0));
+    return encode(vec4<f32>(filtered.rgb * filtered.a, filtered.a));
+}
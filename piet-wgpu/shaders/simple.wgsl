@@ -2,12 +2,17 @@ struct Globals {
     resolution: vec2<f32>,
     scale_factor: f32,
     // texture_dims: vec2<f32>,
+    // the texture drawn into stores colors as written, so they are encoded here
+    srgb_encode: u32,
+    premultiply: u32,
+    _pad: u32,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coord: vec2<f32>,
     @location(1) prim_index: u32,
+    @location(2) coverage: f32,
 };
 
 struct Primitive {
@@ -21,12 +26,16 @@ struct Primitive {
     angle: f32,
     scale: f32,
     z_index: i32,
-    _pad: vec2<u32>,
+    // composited by the pipeline, which is picked per draw call
+    replace: u32,
+    // composited by the renderer, the shader draws into whatever layer is bound
+    layer: u32,
+    _pad: u32,
 };
 
 @group(0) @binding(0) var<uniform> globals: Globals;
 
-@group(1) @binding(0) var<uniform> primitives: array<Primitive, 256>;
+@group(1) @binding(0) var<storage, read> primitives: array<Primitive>;
 
 @group(2) @binding(0) var t_diffuse: texture_2d<f32>;
 @group(2) @binding(1) var s_diffuse: sampler;
@@ -35,6 +44,7 @@ struct Primitive {
 fn vs_main(
     @location(0) position: vec2<f32>,
     @location(1) prim_index: u32,
+    @location(2) coverage: f32,
 ) -> VertexOutput {
     var prim = primitives[prim_index];
     
@@ -45,11 +55,51 @@ fn vs_main(
     var pos_in_bounds = (position - prim.lower_bound) / (prim.upper_bound - prim.lower_bound);
     var tex_coord = prim.tex_coords.xy + (prim.tex_coords.zw - prim.tex_coords.xy) * pos_in_bounds;
 
-    return VertexOutput(vec4<f32>(world_pos, 1.0, 1.0), tex_coord, prim_index);
+    return VertexOutput(vec4<f32>(world_pos, 1.0, 1.0), tex_coord, prim_index, coverage);
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let lower = color / 12.92;
+    let higher = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(higher, lower, color <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let lower = color * 12.92;
+    let higher = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(higher, lower, color <= vec3<f32>(0.0031308));
+}
+
+// colors are blended as the texture drawn into stores them, in linear light for sRGB formats.
+// formats without an sRGB variant get colors encoded here, so they are blended encoded
+fn output(color: vec4<f32>) -> vec4<f32> {
+    var rgb = color.rgb;
+
+    if (globals.srgb_encode != 0u) {
+        rgb = linear_to_srgb(rgb);
+    }
+
+    if (globals.premultiply != 0u) {
+        rgb = rgb * color.a;
+    }
+
+    return vec4<f32>(rgb, color.a);
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     var prim = primitives[in.prim_index];
-    return textureSample(t_diffuse, s_diffuse, in.tex_coord) + prim.color;
+    var texel = textureSample(t_diffuse, s_diffuse, in.tex_coord);
+
+    // piet colors are sRGB, the texture buffer is decoded when sampled
+    var color = vec4<f32>(srgb_to_linear(prim.color.rgb), prim.color.a);
+
+    // primitives without texture coordinates only use their color
+    if (!all(prim.tex_coords == vec4<f32>(0.0))) {
+        color = texel + color;
+    }
+
+    // anti-aliased edges fade out towards the outside of the shape
+    // feathered strokes only fade out towards their edges, coverage above 1 is full
+    return output(vec4<f32>(color.rgb, color.a * min(in.coverage, 1.0)));
 }
@@ -0,0 +1,123 @@
+use wgpu::{BlendComponent, BlendFactor, BlendOperation, BlendState};
+
+/// How a layer is composited onto what is drawn below it, its backdrop.
+///
+/// The Porter-Duff operators, `Plus` and `Screen` are done by the blend unit of the gpu. The
+/// other blend modes read the backdrop in the shader, which takes a copy of it and ends the
+/// render pass drawing the parent layer, so they cost more. Layers blended into the target
+/// itself see what the frame drew as backdrop, not what the target contained before.
+///
+/// Drawing with a blend mode is drawing into a layer with it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum BlendMode {
+    /// Nothing is left inside the layer's clip.
+    Clear,
+    /// The layer replaces the backdrop.
+    Copy,
+    /// Only the backdrop is left.
+    Destination,
+    /// The layer is drawn over the backdrop like any other fill.
+    #[default]
+    SourceOver,
+    /// The backdrop is drawn over the layer.
+    DestinationOver,
+    /// The layer where the backdrop is.
+    SourceIn,
+    /// The backdrop where the layer is.
+    DestinationIn,
+    /// The layer where the backdrop isn't.
+    SourceOut,
+    /// The backdrop where the layer isn't.
+    DestinationOut,
+    /// The layer over the backdrop, only where the backdrop is.
+    SourceAtop,
+    /// The backdrop over the layer, only where the layer is.
+    DestinationAtop,
+    /// The layer and the backdrop where they don't overlap.
+    Xor,
+    /// The sum of both, clamped.
+    Plus,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    Difference,
+    /// The hue of the layer with the saturation and luminosity of the backdrop.
+    Hue,
+    /// The saturation of the layer with the hue and luminosity of the backdrop.
+    Saturation,
+    /// The hue and saturation of the layer with the luminosity of the backdrop.
+    Color,
+    /// The luminosity of the layer with the hue and saturation of the backdrop.
+    Luminosity,
+}
+
+impl BlendMode {
+    /// Blend state compositing a premultiplied layer with this mode, `None` if the shader has to
+    /// blend with a copy of the backdrop.
+    pub(crate) fn blend_state(self) -> Option<BlendState> {
+        use BlendFactor::*;
+
+        let (src, dst) = match self {
+            BlendMode::Clear => (Zero, Zero),
+            BlendMode::Copy => (One, Zero),
+            BlendMode::Destination => (Zero, One),
+            BlendMode::SourceOver => (One, OneMinusSrcAlpha),
+            BlendMode::DestinationOver => (OneMinusDstAlpha, One),
+            BlendMode::SourceIn => (DstAlpha, Zero),
+            BlendMode::DestinationIn => (Zero, SrcAlpha),
+            BlendMode::SourceOut => (OneMinusDstAlpha, Zero),
+            BlendMode::DestinationOut => (Zero, OneMinusSrcAlpha),
+            BlendMode::SourceAtop => (DstAlpha, OneMinusSrcAlpha),
+            BlendMode::DestinationAtop => (OneMinusDstAlpha, SrcAlpha),
+            BlendMode::Xor => (OneMinusDstAlpha, OneMinusSrcAlpha),
+            BlendMode::Plus => (One, One),
+            // cs + cb - cs * cb, and the same for alpha
+            BlendMode::Screen => {
+                return Some(BlendState {
+                    color: component(One, OneMinusSrc),
+                    alpha: component(One, OneMinusSrcAlpha),
+                })
+            }
+            _ => return None,
+        };
+
+        Some(BlendState {
+            color: component(src, dst),
+            alpha: component(src, dst),
+        })
+    }
+
+    /// Number of the blend mode in `composite.wgsl`, for the modes blended by the shader.
+    pub(crate) fn shader_mode(self) -> u32 {
+        match self {
+            BlendMode::Multiply => 1,
+            BlendMode::Overlay => 2,
+            BlendMode::Darken => 3,
+            BlendMode::Lighten => 4,
+            BlendMode::ColorDodge => 5,
+            BlendMode::Difference => 6,
+            BlendMode::Hue => 7,
+            BlendMode::Saturation => 8,
+            BlendMode::Color => 9,
+            BlendMode::Luminosity => 10,
+            _ => 0,
+        }
+    }
+
+    /// Whether compositing reads a copy of the backdrop.
+    pub(crate) fn needs_backdrop(self) -> bool {
+        self.blend_state().is_none()
+    }
+}
+
+fn component(src_factor: BlendFactor, dst_factor: BlendFactor) -> BlendComponent {
+    BlendComponent {
+        src_factor,
+        dst_factor,
+        operation: BlendOperation::Add,
+    }
+}
@@ -0,0 +1,120 @@
+use wgpu::BufferUsages;
+
+use crate::{
+    error::{PietWgpuError, Result},
+    staging::StagingBelt,
+};
+
+/// A gpu buffer that is replaced by a larger one when the uploaded data doesn't fit anymore.
+pub struct GrowableBuffer {
+    label: &'static str,
+    usage: BufferUsages,
+    buffer: wgpu::Buffer,
+    allocations: u64,
+}
+
+impl GrowableBuffer {
+    pub fn new(device: &wgpu::Device, label: &'static str, usage: BufferUsages, size: u64) -> Self {
+        let usage = usage | BufferUsages::COPY_DST;
+
+        Self {
+            label,
+            usage,
+            buffer: Self::create_buffer(device, label, usage, size),
+            allocations: 1,
+        }
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        label: &'static str,
+        usage: BufferUsages,
+        size: u64,
+    ) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: wgpu::util::align_to(
+                size.max(wgpu::COPY_BUFFER_ALIGNMENT),
+                wgpu::COPY_BUFFER_ALIGNMENT,
+            ),
+            usage,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Writes `data` to the start of the buffer through the staging belt, growing it to the next
+    /// power of two if needed. Returns whether the buffer was replaced.
+    pub fn write(
+        &mut self,
+        device: &wgpu::Device,
+        staging_belt: &mut StagingBelt,
+        encoder: &mut wgpu::CommandEncoder,
+        data: &[u8],
+    ) -> Result<bool> {
+        let grown = self.reserve(
+            device,
+            wgpu::util::align_to(data.len() as u64, wgpu::COPY_BUFFER_ALIGNMENT),
+        )?;
+
+        staging_belt.write_buffer(device, encoder, &self.buffer, 0, data);
+
+        Ok(grown)
+    }
+
+    /// Writes `data` at `offset` without growing the buffer, it has to fit already.
+    pub fn write_at(
+        &self,
+        device: &wgpu::Device,
+        staging_belt: &mut StagingBelt,
+        encoder: &mut wgpu::CommandEncoder,
+        offset: u64,
+        data: &[u8],
+    ) {
+        staging_belt.write_buffer(device, encoder, &self.buffer, offset, data);
+    }
+
+    /// Makes sure the buffer can hold at least `size` bytes, the contents are lost when it grows.
+    /// Fails if the device doesn't allow buffers that large.
+    pub fn reserve(&mut self, device: &wgpu::Device, size: u64) -> Result<bool> {
+        if size <= self.size() {
+            return Ok(false);
+        }
+
+        let max = device.limits().max_buffer_size;
+        if size > max {
+            return Err(PietWgpuError::BufferTooLarge {
+                label: self.label,
+                size,
+                max,
+            });
+        }
+
+        // the next power of two may be past the limit even if the size itself isn't
+        let size = size.next_power_of_two().min(max);
+
+        log::debug!(
+            "Growing {} from {} to {size} bytes",
+            self.label,
+            self.size()
+        );
+
+        // the old buffer is released once the gpu is done with it
+        self.buffer = Self::create_buffer(device, self.label, self.usage, size);
+        self.allocations += 1;
+
+        Ok(true)
+    }
+
+    pub fn size(&self) -> u64 {
+        self.buffer.size()
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// Number of buffers allocated, including the initial one.
+    pub fn allocations(&self) -> u64 {
+        self.allocations
+    }
+}
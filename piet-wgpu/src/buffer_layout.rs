@@ -1,4 +1,3 @@
-use image::GenericImageView;
 use kurbo::{Rect, Vec2};
 
 use crate::config::Config;
@@ -17,22 +16,28 @@ impl BufferLayout2D {
     }
 
     pub fn search(&self, size: Vec2) -> Option<Rect> {
-        Some(
-            self.textures
-                .last()
-                .map(|last| Rect {
-                    x0: last.x1,
-                    y0: 0.0,
-                    x1: last.x1 + size.x,
-                    y1: size.y,
-                })
-                .unwrap_or_else(|| Rect {
-                    x0: 0.0,
-                    y0: 0.0,
-                    x1: size.x,
-                    y1: size.y,
-                }),
-        )
+        let rect = self
+            .textures
+            .last()
+            .map(|last| Rect {
+                x0: last.x1,
+                y0: 0.0,
+                x1: last.x1 + size.x,
+                y1: size.y,
+            })
+            .unwrap_or_else(|| Rect {
+                x0: 0.0,
+                y0: 0.0,
+                x1: size.x,
+                y1: size.y,
+            });
+
+        (rect.x1 <= self.size.x && rect.y1 <= self.size.y).then_some(rect)
+    }
+
+    /// Forgets all allocations, the space is handed out again from the start.
+    pub fn clear(&mut self) {
+        self.textures.clear();
     }
 
     pub fn search_and_allocate(&mut self, size: Vec2) -> Option<Rect> {
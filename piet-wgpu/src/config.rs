@@ -1,25 +1,100 @@
+use std::path::PathBuf;
+
 use kurbo::Vec2;
 
 use crate::data::{Primitive, Vertex};
 
+/// Initial sizes of the renderer's buffers in bytes. Vertex, index and primitive buffers grow
+/// when a frame needs more space.
+///
+/// The adapter and device options are ignored by renderers created with a device of the caller.
 #[derive(Clone, Debug)]
 pub struct Config {
     pub vertex_buffer_size: u64,
     pub index_buffer_size: u64,
     pub texture_buffer_dimensions: Vec2,
     pub primitve_buffer_size: u64,
+    /// Binds primitives as uniform buffer in chunks even if the adapter supports storage buffers.
+    pub force_uniform_primitives: bool,
+    /// Size of the staging buffers uploads are copied through, larger uploads get their own.
+    pub staging_chunk_size: u64,
+    /// Backends an adapter is searched on.
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference,
+    /// Only considers software adapters like llvmpipe or WARP.
+    pub force_fallback_adapter: bool,
+    /// Features the device is requested with, the adapter has to support all of them.
+    pub features: wgpu::Features,
+    /// Limits the device is requested with, the adapter has to support all of them. Use
+    /// `wgpu::Limits::downlevel_webgl2_defaults()` for older hardware.
+    pub limits: wgpu::Limits,
+    /// Directory wgpu records an API trace to, if wgpu was built with the `trace` feature.
+    pub trace_path: Option<PathBuf>,
+    /// How frames are presented to a window, `Fifo` is used if the surface doesn't support it.
+    pub present_mode: wgpu::PresentMode,
+    /// How a window is composited with what is behind it, `Auto` is used if the surface
+    /// doesn't support it. Output is premultiplied for `PreMultiplied`.
+    pub alpha_mode: wgpu::CompositeAlphaMode,
+    /// Samples per pixel for multisample anti-aliasing, 1, 2, 4 or 8. wgpu only supports 4
+    /// samples at the moment, which 2 and 8 are clamped to. Falls back to 1 if the target
+    /// format can't be multisampled. Has to match the caller's render pass when drawing with
+    /// `paint`. Frames that aren't cleared build on the previous multisampled frame rather than
+    /// on what is in the target.
+    pub sample_count: u32,
+    /// How the edges of fills are smoothed.
+    pub anti_aliasing: AntiAliasing,
+    /// Kind of format preferred for windows and headless textures, colors are blended the same
+    /// in both.
+    pub surface_format: SurfaceFormat,
+}
+
+/// Anti-aliasing of the edges of fills and strokes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AntiAliasing {
+    /// Edges are only smoothed by multisampling, if `sample_count` is above 1.
+    Multisample,
+    /// Fills and strokes fade out over a border one pixel wide, which works without
+    /// multisampling. Images and clears keep hard edges. Retained nodes keep the border of the
+    /// scale they were drawn at.
+    Edges,
+}
+
+/// Encoding of the texture frames are rendered to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SurfaceFormat {
+    /// The gpu encodes colors to sRGB when writing them.
+    Srgb,
+    /// Colors are stored as written, so frames are drawn into an sRGB texture first that is
+    /// encoded while it's copied into the target.
+    Linear,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             vertex_buffer_size: std::mem::size_of::<Vertex>() as u64 * 1024, // one Vertex is currently 28 bytes
-            index_buffer_size: std::mem::size_of::<u16>() as u64 * 4096,     // indicies are u16
+            // 4096 u16 indices; the immediate renderer switches to u32 once a frame has more
+            // vertices than u16 can index and the retained renderer always uses u32, the buffer
+            // grows for either
+            index_buffer_size: std::mem::size_of::<u16>() as u64 * 4096,
             texture_buffer_dimensions: Vec2 {
                 x: 2048.0,
                 y: 512.0,
             },
             primitve_buffer_size: std::mem::size_of::<Primitive>() as u64 * 512,
+            force_uniform_primitives: false,
+            staging_chunk_size: 1 << 16,
+            backends: wgpu::Backends::all(),
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: false,
+            features: wgpu::Features::empty(),
+            limits: wgpu::Limits::default(),
+            trace_path: None,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            sample_count: 1,
+            anti_aliasing: AntiAliasing::Multisample,
+            surface_format: SurfaceFormat::Srgb,
         }
     }
 }
@@ -1,11 +1,13 @@
-use lyon::lyon_tessellation::FillVertexConstructor;
+use lyon::lyon_tessellation::{FillVertexConstructor, StrokeVertexConstructor};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct Vertex {
     pub position: [f32; 2],
     pub prim_index: u32,
-    pub _pad: u32,
+    /// Share of the pixel covered, below 1 towards the edges of anti-aliased fills. The shader
+    /// clamps it to 1, feathered strokes interpolate from above that.
+    pub coverage: f32,
 }
 
 unsafe impl bytemuck::Pod for Vertex {}
@@ -21,7 +23,17 @@ impl FillVertexConstructor<Vertex> for VertexBuilder {
         Vertex {
             position: [vertex.position().x, vertex.position().y], // z is zero for now
             prim_index: self.prim_index,
-            _pad: 0,
+            coverage: 1.0,
+        }
+    }
+}
+
+impl StrokeVertexConstructor<Vertex> for VertexBuilder {
+    fn new_vertex(&mut self, vertex: lyon::tessellation::StrokeVertex) -> Vertex {
+        Vertex {
+            position: [vertex.position().x, vertex.position().y],
+            prim_index: self.prim_index,
+            coverage: 1.0,
         }
     }
 }
@@ -32,6 +44,10 @@ pub struct Globals {
     pub resolution: [f32; 2],
     pub scale_factor: f32,
     // pub tex_dims: [f32; 4],
+    /// Non-zero if the shader encodes its output to sRGB because the textures it draws into don't.
+    pub srgb_encode: u32,
+    /// Non-zero if the output is premultiplied by alpha.
+    pub premultiply: u32,
     pub _pad: u32,
 }
 
@@ -49,7 +65,12 @@ pub struct Primitive {
     pub angle: f32,            // 4
     pub scale: f32,            // 4
     pub z_index: i32,          // 4
-    pub _pad: [u32; 3],        // 12
+    /// Non-zero if the primitive replaces the target's pixels instead of being composited
+    /// over them, like region clears.
+    pub replace: u32, // 4
+    /// Layer the primitive is drawn into, 0 is the target itself.
+    pub layer: u32, // 4
+    pub _pad: u32,             // 4
                                // 80
 }
 
@@ -63,7 +84,9 @@ impl Primitive {
         angle: 0.0,
         scale: 1.0,
         z_index: 0,
-        _pad: [0, 0, 0],
+        replace: 0,
+        layer: 0,
+        _pad: 0,
     };
 }
 
@@ -75,26 +98,3 @@ impl Default for Primitive {
 
 unsafe impl bytemuck::Pod for Primitive {}
 unsafe impl bytemuck::Zeroable for Primitive {}
-
-#[repr(C)]
-#[derive(Copy, Clone)]
-pub struct GpuVertex {
-    position: [f32; 2],
-    normal: [f32; 2],
-    prim_id: u32,
-}
-
-unsafe impl bytemuck::Pod for GpuVertex {}
-unsafe impl bytemuck::Zeroable for GpuVertex {}
-
-pub struct WithId(pub u32);
-
-impl FillVertexConstructor<GpuVertex> for WithId {
-    fn new_vertex(&mut self, vertex: lyon::tessellation::FillVertex) -> GpuVertex {
-        GpuVertex {
-            position: vertex.position().to_array(),
-            normal: [0.0, 0.0],
-            prim_id: self.0,
-        }
-    }
-}
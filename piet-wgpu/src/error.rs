@@ -6,4 +6,72 @@ pub type Result<T> = std::result::Result<T, PietWgpuError>;
 pub enum PietWgpuError {
     #[error("Error in wgpu pipeline")]
     Pipeline(#[from] wgpu::Error),
+    #[error(
+        "No graphics adapter was found for backends {backends:?}, power preference \
+        {power_preference:?} and force_fallback_adapter {force_fallback_adapter}"
+    )]
+    AdapterNotFound {
+        backends: wgpu::Backends,
+        power_preference: wgpu::PowerPreference,
+        force_fallback_adapter: bool,
+    },
+    #[error("Adapter {adapter} doesn't support the features {missing:?}")]
+    UnsupportedFeatures {
+        adapter: String,
+        missing: wgpu::Features,
+    },
+    #[error(
+        "Adapter {adapter} supports {allowed} for limit {name}, but {requested} was requested"
+    )]
+    UnsupportedLimit {
+        adapter: String,
+        name: &'static str,
+        requested: u64,
+        allowed: u64,
+    },
+    #[error("Failed to request a device from the adapter")]
+    RequestDevice(#[from] wgpu::RequestDeviceError),
+    #[error("The surface is not supported by the adapter")]
+    IncompatibleSurface,
+    #[error("Failed to acquire the next surface texture")]
+    Surface(#[from] wgpu::SurfaceError),
+    #[error("Failed to tessellate geometry")]
+    Tessellation(#[from] lyon::tessellation::TessellationError),
+    #[error("Failed to load image")]
+    Image(#[from] image::ImageError),
+    #[error("Not enough free space for a {width}x{height} texture in the texture buffer")]
+    TextureBufferFull { width: u32, height: u32 },
+    #[error("{label} would need {size} bytes, but the device allows at most {max}")]
+    BufferTooLarge {
+        label: &'static str,
+        size: u64,
+        max: u64,
+    },
+    #[error("The frame has {count} primitives, but the device can bind at most {max}")]
+    TooManyPrimitives { count: u64, max: u64 },
+    #[error("No target view was set for the frame")]
+    MissingTargetView,
+    #[error("The device was lost and can't be recreated for a device of the caller")]
+    DeviceLost,
+    #[error("The surface was lost, the frame was dropped while recreating the gpu resources")]
+    FrameDropped,
+    #[error("Retained draw calls have to happen inside a node")]
+    NoCurrentNode,
+    #[error("There is no open layer to pop")]
+    NoOpenLayer,
+    #[error("Can't read back pixels of format {0:?}")]
+    UnsupportedReadbackFormat(wgpu::TextureFormat),
+    #[error("Screenshots can only be taken between frames")]
+    FrameInProgress,
+    #[error("Failed to write file")]
+    Io(#[from] std::io::Error),
+    #[cfg(feature = "apng")]
+    #[error("Failed to encode animated png")]
+    Apng(#[from] png::EncodingError),
+}
+
+impl From<PietWgpuError> for piet::Error {
+    fn from(error: PietWgpuError) -> Self {
+        piet::Error::BackendError(Box::new(error))
+    }
 }
@@ -0,0 +1,427 @@
+use piet::{kurbo::Vec2, Color};
+
+use crate::buffer::GrowableBuffer;
+
+/// A filter applied to the contents of a layer before it is composited, see
+/// [`RenderContextExt::push_filtered_layer`](crate::RenderContextExt::push_filtered_layer).
+///
+/// Filters work on colors in linear light, whatever format the target has.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Filter {
+    /// Gaussian blur, the radius is the standard deviation like for `blurred_rect`.
+    Blur { radius: f64 },
+    /// The alpha of the layer blurred, moved by `offset` and filled with `color`, drawn below
+    /// the layer.
+    DropShadow {
+        offset: Vec2,
+        blur_radius: f64,
+        color: Color,
+    },
+    /// A 4×5 matrix in row-major order, applied to the unpremultiplied colors. The last column
+    /// is added to red, green, blue and alpha.
+    ColorMatrix([f32; 20]),
+}
+
+impl Filter {
+    /// Desaturates the colors, fully at an `amount` of 1.
+    pub fn grayscale(amount: f64) -> Self {
+        let s = 1.0 - amount.clamp(0.0, 1.0) as f32;
+
+        #[rustfmt::skip]
+        let matrix = [
+            0.2126 + 0.7874 * s, 0.7152 - 0.7152 * s, 0.0722 - 0.0722 * s, 0.0, 0.0,
+            0.2126 - 0.2126 * s, 0.7152 + 0.2848 * s, 0.0722 - 0.0722 * s, 0.0, 0.0,
+            0.2126 - 0.2126 * s, 0.7152 - 0.7152 * s, 0.0722 + 0.9278 * s, 0.0, 0.0,
+            0.0, 0.0, 0.0, 1.0, 0.0,
+        ];
+        Filter::ColorMatrix(matrix)
+    }
+
+    /// Tints the colors brown like old photographs, fully at an `amount` of 1.
+    pub fn sepia(amount: f64) -> Self {
+        let s = 1.0 - amount.clamp(0.0, 1.0) as f32;
+
+        #[rustfmt::skip]
+        let matrix = [
+            0.393 + 0.607 * s, 0.769 - 0.769 * s, 0.189 - 0.189 * s, 0.0, 0.0,
+            0.349 - 0.349 * s, 0.686 + 0.314 * s, 0.168 - 0.168 * s, 0.0, 0.0,
+            0.272 - 0.272 * s, 0.534 - 0.534 * s, 0.131 + 0.869 * s, 0.0, 0.0,
+            0.0, 0.0, 0.0, 1.0, 0.0,
+        ];
+        Filter::ColorMatrix(matrix)
+    }
+
+    /// Multiplies the colors by `amount`, 0 is black and 1 leaves them as they are.
+    pub fn brightness(amount: f64) -> Self {
+        let a = amount.max(0.0) as f32;
+
+        #[rustfmt::skip]
+        let matrix = [
+            a, 0.0, 0.0, 0.0, 0.0,
+            0.0, a, 0.0, 0.0, 0.0,
+            0.0, 0.0, a, 0.0, 0.0,
+            0.0, 0.0, 0.0, 1.0, 0.0,
+        ];
+        Filter::ColorMatrix(matrix)
+    }
+}
+
+/// Fragment shader of a filter pass in `filter.wgsl`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FilterKind {
+    Blur,
+    DropShadow,
+    ColorMatrix,
+}
+
+impl FilterKind {
+    pub fn entry_point(self) -> &'static str {
+        match self {
+            FilterKind::Blur => "fs_blur",
+            FilterKind::DropShadow => "fs_drop_shadow",
+            FilterKind::ColorMatrix => "fs_color_matrix",
+        }
+    }
+}
+
+/// Texture a filter pass reads or writes, the layer's own or one of the scratch textures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Slot {
+    Layer,
+    Scratch(usize),
+}
+
+const SLOTS: [Slot; 3] = [Slot::Layer, Slot::Scratch(0), Slot::Scratch(1)];
+
+/// Parameters of a filter pass the shader reads.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FilterUniform {
+    // column-major, applied to unpremultiplied colors
+    pub color_matrix: [[f32; 4]; 4],
+    pub offset: [f32; 4],
+    pub color: [f32; 4],
+    // one pixel along the blur's axis
+    pub direction: [i32; 2],
+    // the source is read this many pixels back
+    pub shift: [i32; 2],
+    pub sigma: f32,
+    pub taps: i32,
+    // layers of targets the shader encodes for hold sRGB colors, decoded before filtering
+    pub decode_srgb: u32,
+    pub _pad: u32,
+}
+
+/// One fullscreen pass of a filter chain.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilterStep {
+    pub kind: FilterKind,
+    pub uniform: FilterUniform,
+    pub source: Slot,
+    // the layer before the filter, for drop shadows
+    pub original: Slot,
+    pub target: Slot,
+}
+
+/// The passes applying `filters` to a layer and the slot holding the result, which is copied
+/// back to the layer unless it's there already.
+///
+/// `scale` turns the radii and offsets into pixels, `color` turns a piet color into the linear
+/// space filters work in.
+pub fn filter_steps(
+    filters: &[Filter],
+    scale: f64,
+    color: impl Fn(&Color) -> [f32; 4],
+) -> (Vec<FilterStep>, Slot) {
+    let mut steps = Vec::new();
+    let mut current = Slot::Layer;
+
+    // the first slot not taken by the inputs of a pass
+    let free = |taken: &[Option<Slot>]| {
+        SLOTS
+            .into_iter()
+            .find(|slot| !taken.contains(&Some(*slot)))
+            .expect("three slots are enough for two inputs")
+    };
+
+    // `keep` is a slot the passes must not write to
+    let blur = |steps: &mut Vec<FilterStep>, source, keep: Option<Slot>, radius: f64, shift| {
+        let sigma = (radius * scale).max(0.0) as f32;
+        let uniform = FilterUniform {
+            sigma: sigma.max(1e-3),
+            taps: (sigma * 3.0).ceil() as i32,
+            ..FilterUniform::IDENTITY
+        };
+
+        // horizontally with the shift, then vertically
+        let horizontal = free(&[Some(source), keep]);
+        steps.push(FilterStep {
+            kind: FilterKind::Blur,
+            uniform: FilterUniform {
+                direction: [1, 0],
+                shift,
+                ..uniform
+            },
+            source,
+            original: source,
+            target: horizontal,
+        });
+
+        let vertical = free(&[Some(horizontal), keep]);
+        steps.push(FilterStep {
+            kind: FilterKind::Blur,
+            uniform: FilterUniform {
+                direction: [0, 1],
+                ..uniform
+            },
+            source: horizontal,
+            original: horizontal,
+            target: vertical,
+        });
+
+        vertical
+    };
+
+    for filter in filters {
+        current = match *filter {
+            Filter::Blur { radius } => blur(&mut steps, current, None, radius, [0, 0]),
+            Filter::DropShadow {
+                offset,
+                blur_radius,
+                color: ref shadow_color,
+            } => {
+                let offset = offset * scale;
+                let shift = [offset.x.round() as i32, offset.y.round() as i32];
+                let shadow = blur(&mut steps, current, Some(current), blur_radius, shift);
+
+                let target = free(&[Some(shadow), Some(current)]);
+                steps.push(FilterStep {
+                    kind: FilterKind::DropShadow,
+                    uniform: FilterUniform {
+                        color: color(shadow_color),
+                        ..FilterUniform::IDENTITY
+                    },
+                    source: shadow,
+                    original: current,
+                    target,
+                });
+                target
+            }
+            Filter::ColorMatrix(matrix) => {
+                let target = free(&[Some(current)]);
+                steps.push(FilterStep {
+                    kind: FilterKind::ColorMatrix,
+                    uniform: FilterUniform::color_matrix(matrix),
+                    source: current,
+                    original: current,
+                    target,
+                });
+                target
+            }
+        };
+    }
+
+    (steps, current)
+}
+
+impl FilterUniform {
+    const IDENTITY: Self = FilterUniform {
+        color_matrix: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+        offset: [0.0; 4],
+        color: [0.0; 4],
+        direction: [0; 2],
+        shift: [0; 2],
+        sigma: 1.0,
+        taps: 0,
+        decode_srgb: 0,
+        _pad: 0,
+    };
+
+    fn color_matrix(matrix: [f32; 20]) -> Self {
+        let column = |column: usize| std::array::from_fn(|row| matrix[row * 5 + column]);
+
+        FilterUniform {
+            color_matrix: std::array::from_fn(column),
+            offset: column(4),
+            ..FilterUniform::IDENTITY
+        }
+    }
+}
+
+/// A pass of the filters of a frame, ready to be drawn.
+pub struct FilterPass {
+    pub kind: FilterKind,
+    pub bind_group: wgpu::BindGroup,
+    pub target: Slot,
+}
+
+/// The passes filtering a layer and where they leave the result.
+pub struct FilterChain {
+    pub passes: Vec<FilterPass>,
+    pub result: Slot,
+}
+
+/// A texture filters write into before the result ends up in the layer.
+pub struct ScratchTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+/// Scratch textures and uniforms of filter passes, kept from frame to frame.
+pub struct FilterPool {
+    size: (u32, u32),
+    scratch: Vec<ScratchTexture>,
+    pub uniforms: GrowableBuffer,
+    allocations: u64,
+}
+
+impl FilterPool {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            size: (0, 0),
+            scratch: Vec::new(),
+            uniforms: GrowableBuffer::new(
+                device,
+                "Filter Uniform Buffer",
+                wgpu::BufferUsages::UNIFORM,
+                0,
+            ),
+            allocations: 0,
+        }
+    }
+
+    /// Makes sure there are scratch textures as large as the target.
+    pub fn acquire(
+        &mut self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        size: (u32, u32),
+    ) {
+        if self.size == size && !self.scratch.is_empty() {
+            return;
+        }
+
+        let (width, height) = size;
+        self.scratch = (0..2)
+            .map(|_| {
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("Filter Scratch Texture"),
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_SRC,
+                });
+                let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+                ScratchTexture { texture, view }
+            })
+            .collect();
+
+        self.size = size;
+        self.allocations += 2;
+    }
+
+    pub fn scratch(&self, index: usize) -> &ScratchTexture {
+        &self.scratch[index]
+    }
+
+    /// Number of scratch textures and uniform buffers created so far, including replaced ones.
+    pub fn allocations(&self) -> u64 {
+        self.allocations + self.uniforms.allocations()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steps(filters: &[Filter]) -> (Vec<(FilterKind, Slot, Slot, Slot)>, Slot) {
+        let (steps, result) = filter_steps(filters, 2.0, |_| [0.0; 4]);
+        let steps = steps
+            .into_iter()
+            .map(|step| (step.kind, step.source, step.original, step.target))
+            .collect();
+
+        (steps, result)
+    }
+
+    #[test]
+    fn blurs_are_two_passes() {
+        use Slot::*;
+
+        let (steps, result) = steps(&[Filter::Blur { radius: 1.0 }]);
+
+        assert_eq!(
+            steps,
+            [
+                (FilterKind::Blur, Layer, Layer, Scratch(0)),
+                (FilterKind::Blur, Scratch(0), Scratch(0), Layer),
+            ]
+        );
+        assert_eq!(result, Layer);
+    }
+
+    #[test]
+    fn drop_shadows_keep_the_layer_until_drawn_over_the_shadow() {
+        use Slot::*;
+
+        let (steps, result) = steps(&[
+            Filter::grayscale(1.0),
+            Filter::DropShadow {
+                offset: Vec2::new(1.0, 2.0),
+                blur_radius: 1.0,
+                color: Color::BLACK,
+            },
+        ]);
+
+        assert_eq!(
+            steps,
+            [
+                (FilterKind::ColorMatrix, Layer, Layer, Scratch(0)),
+                (FilterKind::Blur, Scratch(0), Scratch(0), Layer),
+                (FilterKind::Blur, Layer, Layer, Scratch(1)),
+                (FilterKind::DropShadow, Scratch(1), Scratch(0), Layer),
+            ]
+        );
+        assert_eq!(result, Layer);
+    }
+
+    #[test]
+    fn shadows_are_shifted_in_pixels() {
+        let (steps, _) = filter_steps(
+            &[Filter::DropShadow {
+                offset: Vec2::new(1.0, -2.0),
+                blur_radius: 1.5,
+                color: Color::BLACK,
+            }],
+            2.0,
+            |_| [0.0; 4],
+        );
+
+        assert_eq!(steps[0].uniform.shift, [2, -4]);
+        assert_eq!(steps[0].uniform.taps, 9);
+        assert_eq!(steps[1].uniform.shift, [0, 0]);
+    }
+
+    #[test]
+    fn color_matrices_are_split_into_columns() {
+        let uniform = FilterUniform::color_matrix(std::array::from_fn(|index| index as f32));
+
+        assert_eq!(uniform.color_matrix[0], [0.0, 5.0, 10.0, 15.0]);
+        assert_eq!(uniform.color_matrix[3], [3.0, 8.0, 13.0, 18.0]);
+        assert_eq!(uniform.offset, [4.0, 9.0, 14.0, 19.0]);
+    }
+}
@@ -0,0 +1,166 @@
+#[cfg(any(feature = "apng", feature = "gif"))]
+use std::{fs::File, io::BufWriter, path::Path};
+use std::{path::PathBuf, time::Duration};
+
+use image::RgbaImage;
+use piet::{Error, RenderContext};
+
+use crate::{error::Result, renderer::WgpuRenderer, PietWgpu};
+
+/// Where a [`FrameRecorder`] writes the frames to.
+#[derive(Clone, Debug)]
+pub enum FrameOutput {
+    /// Numbered png files `frame-0000.png`, `frame-0001.png`, ... in a directory, which is
+    /// created if it doesn't exist.
+    PngSequence(PathBuf),
+    /// An animated png file looping forever.
+    #[cfg(feature = "apng")]
+    Apng(PathBuf),
+    /// An animated gif file looping forever. Gifs have a palette of 256 colors and delays in
+    /// steps of 10ms.
+    #[cfg(feature = "gif")]
+    Gif(PathBuf),
+}
+
+/// Records consecutive frames of an animation, for documentation or to compare them.
+///
+/// Frames are drawn at a fixed simulated timestep rather than the time it takes to render
+/// them, so recordings are the same on every machine. Recording works with any renderer, but
+/// is meant for headless ones.
+#[derive(Clone, Debug)]
+pub struct FrameRecorder {
+    pub frame_count: usize,
+    /// Simulated time between two frames, also the delay of animated outputs.
+    pub timestep: Duration,
+    pub output: FrameOutput,
+}
+
+impl FrameRecorder {
+    pub fn new(frame_count: usize, timestep: Duration, output: FrameOutput) -> Self {
+        Self {
+            frame_count,
+            timestep,
+            output,
+        }
+    }
+
+    /// Simulated time of frame `index`, the first one is at zero.
+    pub fn time(&self, index: usize) -> Duration {
+        self.timestep * index as u32
+    }
+
+    /// Draws each frame with `draw`, which gets the frame index, and writes it to the output.
+    ///
+    /// Every frame is started with `begin_frame`, so `draw` has to draw it in full.
+    pub fn record<T: WgpuRenderer>(
+        &self,
+        piet: &mut PietWgpu<T>,
+        mut draw: impl FnMut(&mut PietWgpu<T>, usize) -> std::result::Result<(), Error>,
+    ) -> std::result::Result<(), Error> {
+        let mut sink = None;
+
+        for index in 0..self.frame_count {
+            piet.begin_frame();
+            draw(piet, index)?;
+            piet.finish()?;
+
+            let frame = piet.screenshot()?;
+            // the size is only known with the first frame
+            let sink = match &mut sink {
+                Some(sink) => sink,
+                None => sink.insert(FrameSink::new(self, frame.width(), frame.height())?),
+            };
+            sink.write(index, &frame)?;
+        }
+
+        if let Some(sink) = sink {
+            sink.finish()?;
+        }
+        Ok(())
+    }
+}
+
+enum FrameSink {
+    PngSequence(PathBuf),
+    #[cfg(feature = "apng")]
+    Apng(png::Writer<BufWriter<File>>),
+    #[cfg(feature = "gif")]
+    Gif(
+        image::codecs::gif::GifEncoder<BufWriter<File>>,
+        image::Delay,
+    ),
+}
+
+impl FrameSink {
+    // only animated pngs need the size up front
+    #[cfg_attr(not(feature = "apng"), allow(unused_variables))]
+    fn new(recorder: &FrameRecorder, width: u32, height: u32) -> Result<Self> {
+        Ok(match &recorder.output {
+            FrameOutput::PngSequence(dir) => {
+                std::fs::create_dir_all(dir)?;
+                FrameSink::PngSequence(dir.clone())
+            }
+            #[cfg(feature = "apng")]
+            FrameOutput::Apng(path) => {
+                let mut encoder = png::Encoder::new(create(path)?, width, height);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(recorder.frame_count as u32, 0)?;
+
+                // delays are fractions of u16, milliseconds unless the timestep is too long
+                let millis = recorder.timestep.as_millis();
+                match u16::try_from(millis) {
+                    Ok(millis) => encoder.set_frame_delay(millis, 1000)?,
+                    Err(_) => encoder.set_frame_delay(
+                        recorder.timestep.as_secs().min(u16::MAX as u64) as u16,
+                        1,
+                    )?,
+                }
+
+                FrameSink::Apng(encoder.write_header()?)
+            }
+            #[cfg(feature = "gif")]
+            FrameOutput::Gif(path) => {
+                let mut encoder = image::codecs::gif::GifEncoder::new(create(path)?);
+                encoder.set_repeat(image::codecs::gif::Repeat::Infinite)?;
+
+                FrameSink::Gif(
+                    encoder,
+                    image::Delay::from_saturating_duration(recorder.timestep),
+                )
+            }
+        })
+    }
+
+    fn write(&mut self, index: usize, frame: &RgbaImage) -> Result<()> {
+        match self {
+            FrameSink::PngSequence(dir) => {
+                frame.save(dir.join(format!("frame-{index:04}.png")))?;
+            }
+            #[cfg(feature = "apng")]
+            FrameSink::Apng(writer) => writer.write_image_data(frame.as_raw())?,
+            #[cfg(feature = "gif")]
+            FrameSink::Gif(encoder, delay) => {
+                encoder.encode_frame(image::Frame::from_parts(frame.clone(), 0, 0, *delay))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            FrameSink::PngSequence(_) => {}
+            #[cfg(feature = "apng")]
+            FrameSink::Apng(writer) => writer.finish()?,
+            // the trailer is written when the encoder is dropped
+            #[cfg(feature = "gif")]
+            FrameSink::Gif(..) => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(feature = "apng", feature = "gif"))]
+fn create(path: &Path) -> Result<BufWriter<File>> {
+    Ok(BufWriter::new(File::create(path)?))
+}
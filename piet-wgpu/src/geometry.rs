@@ -0,0 +1,538 @@
+use kurbo::{PathEl, Rect, Shape};
+use lyon::{
+    lyon_tessellation::{
+        BuffersBuilder, FillOptions, FillTessellator, LineCap, LineJoin, StrokeOptions,
+        StrokeTessellator, StrokeVertex, StrokeVertexConstructor, VertexBuffers,
+    },
+    math::{point, Point, Vector},
+    path::{iterator::PathIterator, Path, PathEvent, Side},
+};
+use piet::StrokeStyle;
+
+use crate::{
+    data::{Vertex, VertexBuilder},
+    error::Result,
+};
+
+const TOLERANCE: f32 = 0.02;
+
+// corners sharper than about 30 degrees get a shorter miter, so spikes don't reach out far
+const MITER_LIMIT: f32 = 4.0;
+
+/// Tessellates the inside of `path`. With a `feather` width the path is shrunk by half of it and
+/// surrounded by a border as wide, whose coverage fades out towards the outside.
+pub fn tesselate_fill(
+    prim_index: u32,
+    path: Path,
+    feather: Option<f32>,
+) -> Result<VertexBuffers<Vertex, u32>> {
+    match feather {
+        Some(width) => tesselate_feathered(prim_index, &path, width),
+        None => tesselate(prim_index, &path),
+    }
+}
+
+fn tesselate(prim_index: u32, path: &Path) -> Result<VertexBuffers<Vertex, u32>> {
+    let mut tesselation_buffer = VertexBuffers::new();
+    let mut fill_tess = FillTessellator::new();
+
+    fill_tess.tessellate(
+        path,
+        &FillOptions::tolerance(TOLERANCE).with_fill_rule(lyon::tessellation::FillRule::NonZero),
+        &mut BuffersBuilder::new(&mut tesselation_buffer, VertexBuilder { prim_index }),
+    )?;
+
+    Ok(tesselation_buffer)
+}
+
+fn tesselate_feathered(
+    prim_index: u32,
+    path: &Path,
+    width: f32,
+) -> Result<VertexBuffers<Vertex, u32>> {
+    let mut inner_path = Path::builder();
+    let mut border = VertexBuffers::<Vertex, u32>::new();
+
+    // holes wind the other way than the outlines around them, so they share the sign of the
+    // whole path's area to find the outside of the fill
+    let polygons = polygons(path);
+    let winding = polygons
+        .iter()
+        .map(|polygon| area(polygon))
+        .sum::<f32>()
+        .signum();
+
+    for polygon in polygons {
+        let offsets = miters(&polygon, winding)
+            .into_iter()
+            .map(|miter| miter * width / 2.0)
+            .collect::<Vec<Vector>>();
+
+        inner_path.begin(polygon[0] - offsets[0]);
+        for (point, offset) in polygon.iter().zip(&offsets).skip(1) {
+            inner_path.line_to(*point - *offset);
+        }
+        inner_path.close();
+
+        // every point gets a vertex inside and outside the outline, edges are quads between them
+        let first = border.vertices.len() as u32;
+        for (point, offset) in polygon.iter().zip(&offsets) {
+            for (position, coverage) in [(*point - *offset, 1.0), (*point + *offset, 0.0)] {
+                border.vertices.push(Vertex {
+                    position: [position.x, position.y],
+                    prim_index,
+                    coverage,
+                });
+            }
+        }
+
+        let len = polygon.len() as u32;
+        for i in 0..len {
+            let (inner, outer) = (first + i * 2, first + i * 2 + 1);
+            let (next_inner, next_outer) =
+                (first + (i + 1) % len * 2, first + (i + 1) % len * 2 + 1);
+
+            border
+                .indices
+                .extend([inner, outer, next_outer, inner, next_outer, next_inner]);
+        }
+    }
+
+    let mut geometry = tesselate(prim_index, &inner_path.build())?;
+
+    let offset = geometry.vertices.len() as u32;
+    geometry.vertices.extend_from_slice(&border.vertices);
+    geometry
+        .indices
+        .extend(border.indices.iter().map(|index| *index + offset));
+
+    Ok(geometry)
+}
+
+/// Tessellates the outline of `path` stroked with `options`. With a `feather` width both halves
+/// of the stroke are tessellated separately, fading out over a border as wide around the edges.
+pub fn tesselate_stroke(
+    prim_index: u32,
+    path: &Path,
+    options: &StrokeOptions,
+    feather: Option<f32>,
+) -> Result<VertexBuffers<Vertex, u32>> {
+    let mut geometry = VertexBuffers::new();
+    let mut stroke_tess = StrokeTessellator::new();
+
+    match feather {
+        Some(width) => {
+            for side in [Side::Positive, Side::Negative] {
+                stroke_tess.tessellate_path(
+                    path,
+                    options,
+                    &mut BuffersBuilder::new(
+                        &mut geometry,
+                        HalfStroke {
+                            prim_index,
+                            side,
+                            feather: width,
+                        },
+                    ),
+                )?;
+            }
+        }
+        None => {
+            stroke_tess.tessellate_path(
+                path,
+                options,
+                &mut BuffersBuilder::new(&mut geometry, VertexBuilder { prim_index }),
+            )?;
+        }
+    }
+
+    Ok(geometry)
+}
+
+/// Vertices of one half of a feathered stroke, those on the other side are moved onto the path.
+///
+/// The coverage falls from above 1 on the path to 0 half a feather width outside of the stroke,
+/// so clamped by the shader it is 1 up to the border and fades out across it.
+struct HalfStroke {
+    prim_index: u32,
+    side: Side,
+    feather: f32,
+}
+
+impl StrokeVertexConstructor<Vertex> for HalfStroke {
+    fn new_vertex(&mut self, vertex: StrokeVertex) -> Vertex {
+        let extent = vertex.line_width() / 2.0 + self.feather / 2.0;
+        let (position, coverage) = match vertex.side() == self.side {
+            true => (vertex.position_on_path() + vertex.normal() * extent, 0.0),
+            false => (vertex.position_on_path(), extent / self.feather),
+        };
+
+        Vertex {
+            position: [position.x, position.y],
+            prim_index: self.prim_index,
+            coverage,
+        }
+    }
+}
+
+/// lyon's options for a stroke of `width` in `style`, the dash pattern is applied by `dash`.
+pub fn stroke_options(width: f64, style: &StrokeStyle) -> StrokeOptions {
+    let cap = match style.line_cap {
+        piet::LineCap::Butt => LineCap::Butt,
+        piet::LineCap::Round => LineCap::Round,
+        piet::LineCap::Square => LineCap::Square,
+    };
+    let (join, miter_limit) = match style.line_join {
+        // lyon doesn't accept limits below 1
+        piet::LineJoin::Miter { limit } => (LineJoin::Miter, limit.max(1.0) as f32),
+        piet::LineJoin::Round => (LineJoin::Round, StrokeOptions::DEFAULT_MITER_LIMIT),
+        piet::LineJoin::Bevel => (LineJoin::Bevel, StrokeOptions::DEFAULT_MITER_LIMIT),
+    };
+
+    StrokeOptions::tolerance(TOLERANCE)
+        .with_line_width(width as f32)
+        .with_line_cap(cap)
+        .with_line_join(join)
+        .with_miter_limit(miter_limit)
+}
+
+/// Splits the subpaths of `path` into dashes, `pattern` alternates between the lengths of dashes
+/// and gaps and starts `offset` into it. Curves are flattened on the way.
+pub fn dash(path: &Path, pattern: &[f64], offset: f64) -> Path {
+    // odd patterns are repeated, so every length is a dash once and a gap once
+    let pattern = match pattern.len() % 2 {
+        0 => pattern.to_vec(),
+        _ => [pattern, pattern].concat(),
+    }
+    .into_iter()
+    .map(|length| length.max(0.0) as f32)
+    .collect::<Vec<f32>>();
+
+    let total = pattern.iter().sum::<f32>();
+    if total <= 0.0 || !total.is_finite() {
+        return path.clone();
+    }
+
+    let mut dasher = Dasher {
+        builder: Path::builder(),
+        pattern: &pattern,
+        index: 0,
+        remaining: 0.0,
+        drawing: false,
+    };
+
+    for event in path.iter().flattened(TOLERANCE) {
+        match event {
+            PathEvent::Begin { at } => dasher.begin(at, (offset as f32).rem_euclid(total)),
+            PathEvent::Line { from, to } => dasher.line(from, to),
+            PathEvent::End { last, first, close } => {
+                if close {
+                    dasher.line(last, first);
+                }
+                dasher.end();
+            }
+            // flattening leaves only lines
+            PathEvent::Quadratic { .. } | PathEvent::Cubic { .. } => {}
+        }
+    }
+
+    dasher.builder.build()
+}
+
+/// Walks along the lines of a subpath, drawing the parts of it that are dashes.
+struct Dasher<'a> {
+    builder: lyon::path::path::Builder,
+    pattern: &'a [f32],
+    // the current dash or gap and how much of it is left
+    index: usize,
+    remaining: f32,
+    drawing: bool,
+}
+
+impl Dasher<'_> {
+    fn begin(&mut self, at: Point, mut offset: f32) {
+        self.index = 0;
+        while offset >= self.pattern[self.index] {
+            offset -= self.pattern[self.index];
+            self.index = (self.index + 1) % self.pattern.len();
+        }
+        self.remaining = self.pattern[self.index] - offset;
+
+        if self.index.is_multiple_of(2) {
+            self.builder.begin(at);
+            self.drawing = true;
+        }
+    }
+
+    fn line(&mut self, mut from: Point, to: Point) {
+        let mut length = (to - from).length();
+        let direction = (to - from) / length.max(f32::EPSILON);
+
+        while length > 0.0 {
+            let step = self.remaining.min(length);
+            from += direction * step;
+            length -= step;
+            self.remaining -= step;
+
+            if self.drawing {
+                self.builder.line_to(from);
+            }
+
+            if self.remaining <= 0.0 {
+                self.index = (self.index + 1) % self.pattern.len();
+                self.remaining = self.pattern[self.index];
+
+                match (self.index.is_multiple_of(2), self.drawing) {
+                    (true, false) => {
+                        self.builder.begin(from);
+                        self.drawing = true;
+                    }
+                    (false, true) => {
+                        self.builder.end(false);
+                        self.drawing = false;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn end(&mut self) {
+        if self.drawing {
+            self.builder.end(false);
+            self.drawing = false;
+        }
+    }
+}
+
+/// `shape` as a lyon path, subpaths are only closed if the shape closes them.
+pub fn shape_path(shape: impl Shape) -> Path {
+    let to_point = |p: kurbo::Point| point(p.x as f32, p.y as f32);
+    let mut builder = Path::builder();
+    let mut start = None;
+    let mut open = false;
+
+    for element in shape.path_elements(TOLERANCE as f64) {
+        // segments after a close start where the closed subpath did
+        if let (PathEl::LineTo(_) | PathEl::QuadTo(..) | PathEl::CurveTo(..), false, Some(start)) =
+            (element, open, start)
+        {
+            builder.begin(start);
+            open = true;
+        }
+
+        match element {
+            PathEl::MoveTo(to) => {
+                if open {
+                    builder.end(false);
+                }
+                builder.begin(to_point(to));
+                start = Some(to_point(to));
+                open = true;
+            }
+            PathEl::LineTo(to) => {
+                builder.line_to(to_point(to));
+            }
+            PathEl::QuadTo(ctrl, to) => {
+                builder.quadratic_bezier_to(to_point(ctrl), to_point(to));
+            }
+            PathEl::CurveTo(ctrl1, ctrl2, to) => {
+                builder.cubic_bezier_to(to_point(ctrl1), to_point(ctrl2), to_point(to));
+            }
+            PathEl::ClosePath => {
+                if open {
+                    builder.close();
+                    open = false;
+                }
+            }
+        }
+    }
+
+    if open {
+        builder.end(false);
+    }
+
+    builder.build()
+}
+
+/// Flattened closed subpaths of `path` without repeated points, degenerate ones are left out.
+fn polygons(path: &Path) -> Vec<Vec<Point>> {
+    let mut polygons = Vec::new();
+    let mut polygon: Vec<Point> = Vec::new();
+
+    for event in path.iter().flattened(TOLERANCE) {
+        match event {
+            PathEvent::Begin { at } => polygon = vec![at],
+            PathEvent::Line { to, .. } => {
+                if polygon
+                    .last()
+                    .is_none_or(|last| (to - *last).length() > 1e-6)
+                {
+                    polygon.push(to);
+                }
+            }
+            PathEvent::End { .. } => {
+                if polygon.len() > 1 && (polygon[0] - polygon[polygon.len() - 1]).length() <= 1e-6 {
+                    polygon.pop();
+                }
+                if polygon.len() > 2 {
+                    polygons.push(std::mem::take(&mut polygon));
+                }
+            }
+            // flattening leaves only lines
+            PathEvent::Quadratic { .. } | PathEvent::Cubic { .. } => {}
+        }
+    }
+
+    polygons
+}
+
+/// Twice the signed area of `polygon`, positive if it winds clockwise in y-down coordinates.
+fn area(polygon: &[Point]) -> f32 {
+    polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(a, b)| a.to_vector().cross(b.to_vector()))
+        .sum::<f32>()
+}
+
+/// Vectors pointing out of the fill at every point of `polygon`, long enough to move both edges
+/// of the corner by one. `winding` is the sign of the area of outlines.
+fn miters(polygon: &[Point], winding: f32) -> Vec<Vector> {
+    // outward is to the left or the right of the edges depending on the winding
+    let normal = |from: Point, to: Point| {
+        let direction = (to - from).normalize();
+        Vector::new(direction.y, -direction.x) * winding
+    };
+
+    let len = polygon.len();
+    (0..len)
+        .map(|i| {
+            let point = polygon[i];
+            let before = normal(polygon[(i + len - 1) % len], point);
+            let after = normal(point, polygon[(i + 1) % len]);
+
+            (before + after) / (1.0 + before.dot(after)).max(2.0 / MITER_LIMIT.powi(2))
+        })
+        .collect()
+}
+
+pub fn rect_path(rect: Rect) -> Path {
+    let mut builder = Path::builder();
+
+    builder.begin(point(rect.x0 as f32, rect.y0 as f32));
+    builder.line_to(point(rect.x0 as f32, rect.y1 as f32));
+    builder.line_to(point(rect.x1 as f32, rect.y1 as f32));
+    builder.line_to(point(rect.x1 as f32, rect.y0 as f32));
+
+    builder.close();
+
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use kurbo::{BezPath, Circle, Line};
+
+    use super::*;
+
+    /// First and last point of every subpath.
+    fn subpath_ends(path: &Path) -> Vec<(Point, Point)> {
+        path.iter()
+            .filter_map(|event| match event {
+                PathEvent::End { last, first, .. } => Some((first, last)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shapes_keep_their_subpaths() {
+        let circle = shape_path(Circle::new((0.0, 0.0), 4.0));
+        assert!(circle
+            .iter()
+            .any(|event| matches!(event, PathEvent::End { close: true, .. })));
+
+        let line = shape_path(Line::new((0.0, 0.0), (4.0, 0.0)));
+        assert_eq!(subpath_ends(&line), [(point(0.0, 0.0), point(4.0, 0.0))]);
+    }
+
+    #[test]
+    fn dashes_alternate_with_gaps() {
+        let line = shape_path(Line::new((0.0, 0.0), (10.0, 0.0)));
+
+        let dashes = subpath_ends(&dash(&line, &[2.0, 3.0], 0.0));
+        assert_eq!(
+            dashes,
+            [
+                (point(0.0, 0.0), point(2.0, 0.0)),
+                (point(5.0, 0.0), point(7.0, 0.0)),
+                (point(10.0, 0.0), point(10.0, 0.0)),
+            ]
+        );
+
+        // odd patterns repeat, the offset moves into the first gap
+        let dashes = subpath_ends(&dash(&line, &[2.0], 3.0));
+        assert_eq!(
+            dashes,
+            [
+                (point(1.0, 0.0), point(3.0, 0.0)),
+                (point(5.0, 0.0), point(7.0, 0.0)),
+                (point(9.0, 0.0), point(10.0, 0.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn feathered_strokes_fade_out_at_their_edges() {
+        let line = shape_path(Line::new((0.0, 0.0), (10.0, 0.0)));
+        let options = stroke_options(4.0, &StrokeStyle::default());
+
+        let geometry = tesselate_stroke(0, &line, &options, Some(1.0)).unwrap();
+        for vertex in &geometry.vertices {
+            let [_, y] = vertex.position;
+            match y.abs() {
+                // the border is centered on the edge of the stroke
+                y if (y - 2.5).abs() < 1e-4 => assert_eq!(vertex.coverage, 0.0),
+                y if y < 1e-4 => assert_eq!(vertex.coverage, 2.5),
+                _ => panic!("vertex off the path and the border: {:?}", vertex.position),
+            }
+        }
+
+        let solid = tesselate_stroke(0, &line, &options, None).unwrap();
+        assert!(solid
+            .vertices
+            .iter()
+            .all(|vertex| vertex.position[1].abs() == 2.0 && vertex.coverage == 1.0));
+    }
+
+    #[test]
+    fn holes_fade_out_into_the_hole() {
+        // a square with a square hole, wound the other way
+        let mut shape = BezPath::new();
+        for (corners, reverse) in [((0.0, 10.0), false), ((3.0, 7.0), true)] {
+            let (low, high) = corners;
+            let mut points = [(low, low), (high, low), (high, high), (low, high)];
+            if reverse {
+                points.reverse();
+            }
+            shape.move_to(points[0]);
+            points[1..].iter().for_each(|point| shape.line_to(*point));
+            shape.close_path();
+        }
+
+        let geometry = tesselate_fill(0, shape_path(shape), Some(1.0)).unwrap();
+        let faded = geometry
+            .vertices
+            .iter()
+            .filter(|vertex| vertex.coverage == 0.0)
+            .map(|vertex| vertex.position);
+
+        // outside of the square or inside of the hole
+        for [x, y] in faded {
+            let outside = !(0.0..=10.0).contains(&x) || !(0.0..=10.0).contains(&y);
+            let in_hole = (3.0..7.0).contains(&x) && (3.0..7.0).contains(&y);
+            assert!(outside || in_hole, "faded vertex at {x}, {y}");
+        }
+    }
+}
@@ -0,0 +1,1869 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    num::NonZeroU64,
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use image::RgbaImage;
+use kurbo::{Rect, Vec2};
+use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
+use wgpu::{
+    BindGroupDescriptor, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
+    BufferUsages, ShaderStages,
+};
+
+use crate::{
+    blend::BlendMode,
+    buffer::GrowableBuffer,
+    buffer_layout::BufferLayout2D,
+    config::{AntiAliasing, Config, SurfaceFormat},
+    data::{Globals, Primitive, Vertex},
+    error::{PietWgpuError, Result},
+    filter::{filter_steps, FilterChain, FilterKind, FilterPass, FilterPool, FilterUniform, Slot},
+    layer::{scissor_rect, Layer, LayerPlan, LayerPool, LayerTexture, LayerUniform, Step},
+    screenshot,
+    shader::{PrimitiveBinding, COMPOSITE_SHADER, FILTER_SHADER},
+    staging::StagingBelt,
+    target::RenderTarget,
+    WgpuImage,
+};
+
+/// Device, pipeline and the gpu resources shared by the renderers. The renderers decide what
+/// ends up in the vertex, index and primitive buffers and which draw calls are made.
+pub struct GpuState {
+    pub scale: f64,
+    // both unknown when rendering with a device of the caller
+    #[allow(dead_code)] // kept alive alongside the surface created from it
+    instance: Option<wgpu::Instance>,
+    #[allow(dead_code)]
+    adapter: Option<wgpu::Adapter>,
+    pub device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    pub encoder: wgpu::CommandEncoder,
+    pub staging_belt: StagingBelt,
+    recall_pending: bool,
+    pub target: RenderTarget,
+    pub vertex_buffer: GrowableBuffer,
+    pub index_buffer: GrowableBuffer,
+    pub prim_buffer: GrowableBuffer,
+    pub prim_binding: PrimitiveBinding,
+    prim_buffer_bind_group_layout: BindGroupLayout,
+    prim_bind_group: wgpu::BindGroup,
+    texture_buffer: wgpu::Texture, // one buffer for all images
+    texture_bind_group: wgpu::BindGroup,
+    texture_buffer_layout: BufferLayout2D,
+    // where images are in the texture buffer by image id, kept across frames
+    atlas_entries: HashMap<u64, AtlasEntry>,
+    atlas_full: bool,
+    // errors wgpu would otherwise panic on, reported after the frame is submitted
+    uncaptured_error: Arc<Mutex<Option<wgpu::Error>>>,
+    rebuilt: bool,
+    globals_buffer: wgpu::Buffer,
+    globals_bind_group: wgpu::BindGroup,
+    // fills are composited over the target, region clears replace it
+    pipeline: wgpu::RenderPipeline,
+    replace_pipeline: wgpu::RenderPipeline,
+    // layers are drawn into textures of the pool and composited onto their parent, with a
+    // pipeline by blend state created once a frame needs it, `None` blends in the shader
+    composite_shader: wgpu::ShaderModule,
+    composite_pipelines:
+        HashMap<(Option<wgpu::BlendState>, wgpu::TextureFormat), wgpu::RenderPipeline>,
+    layer_bind_group_layout: BindGroupLayout,
+    backdrop_bind_group_layout: BindGroupLayout,
+    layer_pool: LayerPool,
+    // what goes into which layer in the last frame
+    layer_plan: LayerPlan,
+    // filters of layers are fullscreen passes between the layer and scratch textures
+    filter_shader: wgpu::ShaderModule,
+    filter_pipelines: HashMap<FilterKind, wgpu::RenderPipeline>,
+    filter_bind_group_layout: BindGroupLayout,
+    filter_pool: FilterPool,
+    // by layer, for the last frame
+    filter_chains: HashMap<u32, FilterChain>,
+    sample_count: u32,
+    // rendered into and resolved to the target's frame if multisampling is enabled
+    multisample_view: Option<wgpu::TextureView>,
+    // the target keeps its content without a clear color
+    pub clear_color: Option<wgpu::Color>,
+    config: Config,
+}
+
+impl GpuState {
+    pub fn from_window<W: HasRawWindowHandle + HasRawDisplayHandle>(
+        window: &W,
+        width: u32,
+        height: u32,
+        scale: f64,
+        config: Config,
+    ) -> Result<Self> {
+        let instance = wgpu::Instance::new(config.backends);
+        let surface = unsafe { instance.create_surface(window) };
+        let (adapter, device, queue) = Self::request_device(&instance, Some(&surface), &config)?;
+
+        let formats = surface.get_supported_formats(&adapter);
+        let format = *formats
+            .iter()
+            .find(|format| format.describe().srgb == (config.surface_format == SurfaceFormat::Srgb))
+            .or_else(|| formats.first())
+            .ok_or(PietWgpuError::IncompatibleSurface)?;
+
+        let present_mode = match surface
+            .get_supported_present_modes(&adapter)
+            .contains(&config.present_mode)
+        {
+            true => config.present_mode,
+            false => {
+                log::warn!(
+                    "{:?} isn't supported by the surface, using Fifo",
+                    config.present_mode
+                );
+                wgpu::PresentMode::Fifo
+            }
+        };
+
+        let alpha_mode = match surface
+            .get_supported_alpha_modes(&adapter)
+            .contains(&config.alpha_mode)
+        {
+            true => config.alpha_mode,
+            false => {
+                log::warn!(
+                    "{:?} isn't supported by the surface, using Auto",
+                    config.alpha_mode
+                );
+                wgpu::CompositeAlphaMode::Auto
+            }
+        };
+
+        let target = RenderTarget::surface(
+            &device,
+            surface,
+            wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                format,
+                width,
+                height,
+                present_mode,
+                alpha_mode,
+            },
+        );
+
+        Self::from_target(
+            Some(instance),
+            Some(adapter),
+            Arc::new(device),
+            Arc::new(queue),
+            target,
+            scale,
+            config,
+        )
+    }
+
+    /// Renders into an offscreen texture instead of a window.
+    pub fn headless(width: u32, height: u32, scale: f64, config: Config) -> Result<Self> {
+        let instance = wgpu::Instance::new(config.backends);
+        let (adapter, device, queue) = Self::request_device(&instance, None, &config)?;
+
+        let format = match config.surface_format {
+            SurfaceFormat::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            SurfaceFormat::Linear => wgpu::TextureFormat::Rgba8Unorm,
+        };
+
+        let target = RenderTarget::texture(&device, format, width, height);
+
+        Self::from_target(
+            Some(instance),
+            Some(adapter),
+            Arc::new(device),
+            Arc::new(queue),
+            target,
+            scale,
+            config,
+        )
+    }
+
+    /// Renders with the device of the caller into the views passed to `set_target_view`.
+    pub fn from_device(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        scale: f64,
+        config: Config,
+    ) -> Result<Self> {
+        let target = RenderTarget::view(format, width, height);
+
+        Self::from_target(None, None, device, queue, target, scale, config)
+    }
+
+    fn request_device(
+        instance: &wgpu::Instance,
+        compatible_surface: Option<&wgpu::Surface>,
+        config: &Config,
+    ) -> Result<(wgpu::Adapter, wgpu::Device, wgpu::Queue)> {
+        let adapter =
+            futures::executor::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: config.power_preference,
+                compatible_surface,
+                force_fallback_adapter: config.force_fallback_adapter,
+            }))
+            .ok_or(PietWgpuError::AdapterNotFound {
+                backends: config.backends,
+                power_preference: config.power_preference,
+                force_fallback_adapter: config.force_fallback_adapter,
+            })?;
+
+        let adapter_name = adapter.get_info().name;
+
+        // checked up front, wgpu would only report them as an unspecific device request error
+        let missing = config.features - adapter.features();
+        if !missing.is_empty() {
+            return Err(PietWgpuError::UnsupportedFeatures {
+                adapter: adapter_name,
+                missing,
+            });
+        }
+
+        let mut unsupported_limit = None;
+        config.limits.check_limits_with_fail_fn(
+            &adapter.limits(),
+            false,
+            |name, requested, allowed| {
+                unsupported_limit.get_or_insert((name, requested, allowed));
+            },
+        );
+
+        if let Some((name, requested, allowed)) = unsupported_limit {
+            return Err(PietWgpuError::UnsupportedLimit {
+                adapter: adapter_name,
+                name,
+                requested,
+                allowed,
+            });
+        }
+
+        let (device, queue) = futures::executor::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features: config.features,
+                limits: config.limits.clone(),
+                label: None,
+            },
+            config.trace_path.as_deref(),
+        ))?;
+
+        Ok((adapter, device, queue))
+    }
+
+    fn from_target(
+        instance: Option<wgpu::Instance>,
+        adapter: Option<wgpu::Adapter>,
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        target: RenderTarget,
+        scale: f64,
+        config: Config,
+    ) -> Result<Self> {
+        let encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        });
+
+        let globals_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Globals Buffer"),
+            size: std::mem::size_of::<Globals>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let globals_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("Global Bind Group Layout"),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(std::mem::size_of::<Globals>() as u64),
+                    },
+                    count: None,
+                }],
+            });
+
+        let globals_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Globals Bind Group"),
+            layout: &globals_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(globals_buffer.as_entire_buffer_binding()),
+            }],
+        });
+
+        let prim_binding = PrimitiveBinding::new(
+            adapter.as_ref(),
+            &device.limits(),
+            config.force_uniform_primitives,
+        );
+
+        let simple_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Simple vs"),
+            source: wgpu::ShaderSource::Wgsl(prim_binding.shader_source()),
+        });
+
+        let vertex_buffer_layout = wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Uint32, 2 => Float32],
+        };
+
+        let vertex_buffer = GrowableBuffer::new(
+            &device,
+            "Vertex Buffer",
+            BufferUsages::VERTEX,
+            config.vertex_buffer_size,
+        );
+
+        let prim_buffer = GrowableBuffer::new(
+            &device,
+            "Primitive Buffer",
+            prim_binding.buffer_usage(),
+            config
+                .primitve_buffer_size
+                .max(prim_binding.min_buffer_size()),
+        );
+
+        let prim_buffer_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("Primitives Buffer Layout"),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX_FRAGMENT,
+                    ty: prim_binding.binding_type(),
+                    count: None,
+                }],
+            });
+
+        let prim_bind_group = create_prim_bind_group(
+            &device,
+            &prim_buffer_bind_group_layout,
+            &prim_buffer,
+            prim_binding,
+        );
+
+        let index_buffer = GrowableBuffer::new(
+            &device,
+            "Index Buffer",
+            BufferUsages::INDEX,
+            config.index_buffer_size,
+        );
+
+        let texture_buffer_layout = BufferLayout2D::new(&config);
+
+        let texture_size = wgpu::Extent3d {
+            width: config.texture_buffer_dimensions.x as u32,
+            height: config.texture_buffer_dimensions.y as u32,
+            depth_or_array_layers: 1,
+        };
+
+        let texture_buffer = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("diffuse_texture"),
+            size: texture_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        let texture_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+                label: Some("texture_bind_group_layout"),
+            });
+
+        let texture_view = texture_buffer.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("diffuse_bind_group"),
+            layout: &texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture_sampler),
+                },
+            ],
+        });
+
+        let sample_count = sample_count(adapter.as_ref(), target.format(), config.sample_count);
+        let multisample_view = create_multisample_view(&device, &target, sample_count);
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[
+                    &globals_bind_group_layout,
+                    &prim_buffer_bind_group_layout,
+                    &texture_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let create_pipeline = |label, blend| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&render_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &simple_shader,
+                    entry_point: "vs_main",
+                    buffers: std::slice::from_ref(&vertex_buffer_layout),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &simple_shader,
+                    entry_point: "fs_main",
+                    // everything is drawn into layers on targets storing colors as written
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: target.layer_format(),
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    // the fringes of anti-aliased holes are wound the other way around
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
+        };
+
+        let pipeline = create_pipeline(
+            "Render Pipeline",
+            match target.premultiplied_alpha() {
+                true => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+                false => wgpu::BlendState::ALPHA_BLENDING,
+            },
+        );
+        let replace_pipeline = create_pipeline("Replace Pipeline", wgpu::BlendState::REPLACE);
+
+        let layer_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Layer Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    // the uniforms of all layers share a buffer
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: NonZeroU64::new(
+                            std::mem::size_of::<LayerUniform>() as u64
+                        ),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    // layers are read pixel by pixel, without a sampler
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let backdrop_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("Backdrop Bind Group Layout"),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                }],
+            });
+
+        let composite_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Composite Shader"),
+            source: wgpu::ShaderSource::Wgsl(COMPOSITE_SHADER.into()),
+        });
+
+        // filters read their input and the layer before the filter without a sampler
+        let filter_texture_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        };
+        let filter_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("Filter Bind Group Layout"),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: NonZeroU64::new(
+                                std::mem::size_of::<FilterUniform>() as u64
+                            ),
+                        },
+                        count: None,
+                    },
+                    filter_texture_entry(1),
+                    filter_texture_entry(2),
+                ],
+            });
+
+        let filter_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Filter Shader"),
+            source: wgpu::ShaderSource::Wgsl(FILTER_SHADER.into()),
+        });
+        let layer_pool = LayerPool::new(&device);
+        let filter_pool = FilterPool::new(&device);
+
+        let clear_color = target.default_clear_color();
+
+        // the error handler of a caller's device is left alone
+        let uncaptured_error = Arc::new(Mutex::new(None));
+        if instance.is_some() {
+            let uncaptured_error = uncaptured_error.clone();
+
+            device.on_uncaptured_error(move |error| {
+                if let Ok(mut uncaptured_error) = uncaptured_error.lock() {
+                    uncaptured_error.get_or_insert(error);
+                }
+            });
+        }
+
+        Ok(Self {
+            scale,
+            instance,
+            adapter,
+            device,
+            queue,
+            encoder,
+            staging_belt: StagingBelt::new(config.staging_chunk_size),
+            recall_pending: false,
+            target,
+            pipeline,
+            replace_pipeline,
+            composite_shader,
+            composite_pipelines: HashMap::new(),
+            layer_bind_group_layout,
+            backdrop_bind_group_layout,
+            layer_pool,
+            layer_plan: LayerPlan::default(),
+            filter_shader,
+            filter_pipelines: HashMap::new(),
+            filter_bind_group_layout,
+            filter_pool,
+            filter_chains: HashMap::new(),
+            sample_count,
+            multisample_view,
+            vertex_buffer,
+            index_buffer,
+            prim_buffer,
+            prim_binding,
+            prim_buffer_bind_group_layout,
+            prim_bind_group,
+            texture_buffer,
+            texture_bind_group,
+            texture_buffer_layout,
+            atlas_entries: HashMap::new(),
+            atlas_full: false,
+            uncaptured_error,
+            rebuilt: false,
+            globals_buffer,
+            globals_bind_group,
+            clear_color,
+            config,
+        })
+    }
+
+    /// Width of the border edges of fills fade out in with edge anti-aliasing, one pixel.
+    pub fn feather_width(&self) -> Option<f32> {
+        match self.config.anti_aliasing {
+            AntiAliasing::Multisample => None,
+            AntiAliasing::Edges => Some((1.0 / self.scale) as f32),
+        }
+    }
+
+    /// Resizes the target along with the multisampled texture rendered into before it.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.target.resize(&self.device, width, height);
+        self.multisample_view =
+            create_multisample_view(&self.device, &self.target, self.sample_count);
+    }
+
+    pub fn set_target_view(&mut self, view: wgpu::TextureView) {
+        self.target.set_view(view);
+    }
+
+    /// Recreates the primitive bind group, which still refers to the old buffer after it grew.
+    pub fn refresh_prim_bind_group(&mut self) {
+        self.prim_bind_group = create_prim_bind_group(
+            &self.device,
+            &self.prim_buffer_bind_group_layout,
+            &self.prim_buffer,
+            self.prim_binding,
+        );
+    }
+
+    /// Texture coordinates of `image` in the texture buffer, uploading it if it isn't there yet.
+    pub fn atlas_coords(&mut self, image: &WgpuImage) -> Result<[f32; 4]> {
+        let rgba_image = &image.buffer;
+
+        let buffer_pos = match self.atlas_entries.get(&image.id) {
+            Some(entry) => entry.rect,
+            None => {
+                let buffer_pos = self
+                    .texture_buffer_layout
+                    .search_and_allocate(Vec2 {
+                        x: rgba_image.width() as f64,
+                        y: rgba_image.height() as f64,
+                    })
+                    .ok_or_else(|| {
+                        self.atlas_full = true;
+
+                        PietWgpuError::TextureBufferFull {
+                            width: rgba_image.width(),
+                            height: rgba_image.height(),
+                        }
+                    })?;
+
+                let entry = AtlasEntry {
+                    rect: buffer_pos,
+                    image: rgba_image.clone(),
+                };
+
+                entry.upload(
+                    &self.device,
+                    &mut self.staging_belt,
+                    &mut self.encoder,
+                    &self.texture_buffer,
+                );
+
+                self.atlas_entries.insert(image.id, entry);
+
+                buffer_pos
+            }
+        };
+
+        Ok([
+            (buffer_pos.x0 / self.config.texture_buffer_dimensions.x) as f32,
+            (buffer_pos.y0 / self.config.texture_buffer_dimensions.y) as f32,
+            (buffer_pos.x1 / self.config.texture_buffer_dimensions.x) as f32,
+            (buffer_pos.y1 / self.config.texture_buffer_dimensions.y) as f32,
+        ])
+    }
+
+    /// Empties the texture buffer if an image didn't fit into it.
+    ///
+    /// Images can't be evicted one by one, so a full atlas starts over and images drawn
+    /// afterwards are uploaded again. Coordinates handed out before are invalid afterwards.
+    pub fn reset_full_atlas(&mut self) {
+        if self.atlas_full {
+            self.clear_atlas();
+        }
+    }
+
+    /// Forgets every image in the texture buffer, they are uploaded again when drawn next.
+    pub fn clear_atlas(&mut self) {
+        self.texture_buffer_layout.clear();
+        self.atlas_entries.clear();
+        self.atlas_full = false;
+    }
+
+    /// Whether the gpu resources were recreated since the last call, everything kept in gpu
+    /// buffers has to be uploaded again.
+    pub fn take_rebuilt(&mut self) -> bool {
+        std::mem::take(&mut self.rebuilt)
+    }
+
+    /// Reports errors wgpu ran into since the last check, recovering from device loss.
+    fn check_errors(&mut self) -> Result<()> {
+        let error = match self.uncaptured_error.lock() {
+            Ok(mut uncaptured_error) => uncaptured_error.take(),
+            Err(_) => None,
+        };
+
+        match error {
+            Some(error) if is_device_lost(&error) => self.rebuild(),
+            Some(error) => Err(error.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Replaces a lost device with a new one and creates all gpu resources on it again.
+    ///
+    /// Images in the texture buffer are uploaded again from their cpu copies, so texture
+    /// coordinates handed out before stay valid. Devices of the caller can't be replaced.
+    fn rebuild(&mut self) -> Result<()> {
+        log::warn!("The device was lost, recreating gpu resources");
+
+        let instance = self.instance.as_ref().ok_or(PietWgpuError::DeviceLost)?;
+        let (adapter, device, queue) =
+            Self::request_device(instance, self.target.window_surface(), &self.config)?;
+
+        let (width, height) = self.target.size();
+        let placeholder = RenderTarget::view(self.target.format(), width, height);
+        let mut target = std::mem::replace(&mut self.target, placeholder);
+        target.recreate(&device);
+
+        let mut state = Self::from_target(
+            self.instance.take(),
+            Some(adapter),
+            Arc::new(device),
+            Arc::new(queue),
+            target,
+            self.scale,
+            self.config.clone(),
+        )?;
+
+        std::mem::swap(
+            &mut state.texture_buffer_layout,
+            &mut self.texture_buffer_layout,
+        );
+        std::mem::swap(&mut state.atlas_entries, &mut self.atlas_entries);
+
+        for entry in state.atlas_entries.values() {
+            entry.upload(
+                &state.device,
+                &mut state.staging_belt,
+                &mut state.encoder,
+                &state.texture_buffer,
+            );
+        }
+
+        state.atlas_full = self.atlas_full;
+        state.clear_color = self.clear_color;
+        state.rebuilt = true;
+
+        *self = state;
+
+        Ok(())
+    }
+
+    /// Number of gpu buffers allocated so far, including staging buffers and buffers replaced
+    /// by larger ones.
+    pub fn allocations(&self) -> u64 {
+        self.staging_belt.allocations()
+            + self.vertex_buffer.allocations()
+            + self.index_buffer.allocations()
+            + self.prim_buffer.allocations()
+            + self.layer_pool.allocations()
+            + self.filter_pool.allocations()
+    }
+
+    /// Hands back staging buffers the gpu is done with, called before uploading a frame.
+    pub fn poll(&mut self) {
+        // chunks used by an encoder of the caller can only be recalled once it was submitted,
+        // which happened by the time the next frame is uploaded
+        if self.recall_pending {
+            self.staging_belt.recall();
+            self.recall_pending = false;
+        }
+
+        self.device.poll(wgpu::Maintain::Poll);
+    }
+
+    /// Takes the encoder holding the uploads recorded while drawing, a new one takes its place.
+    pub fn take_encoder(&mut self) -> wgpu::CommandEncoder {
+        // create and swap encoders to work around finish() consuming the encoder
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+
+        std::mem::swap(&mut self.encoder, &mut encoder);
+
+        encoder
+    }
+
+    fn write_globals(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        let (width, height) = self.target.size();
+
+        // TODO move to set_size or something
+        let globals = Globals {
+            resolution: [width as f32, height as f32],
+            scale_factor: self.scale as f32,
+            srgb_encode: !self.target.layers_encode_srgb() as u32,
+            premultiply: self.target.premultiplied_alpha() as u32,
+            _pad: 0,
+        };
+
+        self.staging_belt.write_buffer(
+            device,
+            encoder,
+            &self.globals_buffer,
+            0,
+            bytemuck::cast_slice(&[globals]),
+        );
+    }
+
+    /// Finishes the uploads of a frame recorded into `encoder` of the caller.
+    ///
+    /// `upload_encoder` is the one returned by `take_encoder`, its uploads are submitted right
+    /// away so they land before the caller's encoder.
+    ///
+    /// Layers are rendered into their textures with `encoder` as well, `paint` only composites
+    /// them.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        upload_encoder: wgpu::CommandEncoder,
+        index_format: wgpu::IndexFormat,
+        draw_calls: &[DrawCall],
+        layers: &[Layer],
+    ) -> Result<()> {
+        self.write_globals(device, encoder);
+        // the uploads are submitted either way, so the staging belt can recall its buffers
+        let prepared = self.prepare_layers(device, encoder, draw_calls, layers, true);
+        if prepared.is_ok() {
+            self.draw_layers(encoder, index_format, draw_calls);
+        }
+
+        self.staging_belt.finish();
+        queue.submit(std::iter::once(upload_encoder.finish()));
+        self.recall_pending = true;
+
+        prepared?;
+        self.check_errors()
+    }
+
+    /// Records the draw calls of the target itself into a render pass, the buffers have to be
+    /// uploaded and the layers rendered already.
+    pub fn paint<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        index_format: wgpu::IndexFormat,
+        draw_calls: &[DrawCall],
+    ) {
+        // layers blended with the backdrop of the target are in the plan's root layer
+        let steps = self.layer_plan.steps.first().map_or(&[][..], Vec::as_slice);
+        self.paint_steps(render_pass, index_format, draw_calls, steps);
+    }
+
+    fn paint_steps<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        index_format: wgpu::IndexFormat,
+        draw_calls: &[DrawCall],
+        steps: &[Step],
+    ) {
+        self.bind_shared(render_pass, index_format);
+
+        let mut replace = None;
+
+        for step in steps {
+            let range = match step {
+                Step::Draw(range) => range.clone(),
+                Step::Composite(child) => {
+                    self.composite(render_pass, *child);
+
+                    // the composite pipeline has bind groups of its own
+                    self.bind_shared(render_pass, index_format);
+                    replace = None;
+                    continue;
+                }
+            };
+
+            for draw_call in &draw_calls[range] {
+                if replace != Some(draw_call.replace) {
+                    render_pass.set_pipeline(match draw_call.replace {
+                        true => &self.replace_pipeline,
+                        false => &self.pipeline,
+                    });
+                    replace = Some(draw_call.replace);
+                }
+
+                match self.prim_binding {
+                    PrimitiveBinding::Storage => {
+                        render_pass.set_bind_group(1, &self.prim_bind_group, &[])
+                    }
+                    PrimitiveBinding::Uniform { .. } => render_pass.set_bind_group(
+                        1,
+                        &self.prim_bind_group,
+                        &[draw_call.prim_offset],
+                    ),
+                }
+
+                render_pass.draw_indexed(draw_call.indices.clone(), 0, 0..1);
+            }
+        }
+    }
+
+    fn bind_shared<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        index_format: wgpu::IndexFormat,
+    ) {
+        render_pass.set_bind_group(0, &self.globals_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.texture_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.buffer().slice(..));
+        render_pass.set_index_buffer(self.index_buffer.buffer().slice(..), index_format);
+    }
+
+    /// Draws the texture of a finished layer onto the one the render pass draws into.
+    fn composite<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, layer: u32) {
+        let Some(texture) = self.layer_plan.textures[layer as usize] else {
+            return;
+        };
+        let texture = self.layer_pool.get(texture);
+        let Some([x, y, width, height]) = self.composite_rect(layer) else {
+            return;
+        };
+        let blend_mode = self.layer_plan.layers[layer as usize].blend_mode;
+        // children of the target are composited into it, the others into their parent layer
+        let format = match self.layer_plan.layers[layer as usize].parent {
+            0 => self.target.format(),
+            _ => self.target.layer_format(),
+        };
+
+        render_pass.set_scissor_rect(x, y, width, height);
+        render_pass.set_pipeline(&self.composite_pipelines[&(blend_mode.blend_state(), format)]);
+        render_pass.set_bind_group(
+            0,
+            &texture.bind_group,
+            &[self.layer_pool.uniform_offset(layer)],
+        );
+        if blend_mode.needs_backdrop() {
+            let backdrop = self
+                .layer_pool
+                .backdrop()
+                .expect("backdrop taken with the layers");
+            render_pass.set_bind_group(1, &backdrop.bind_group, &[]);
+        }
+        render_pass.draw(0..3, 0..1);
+
+        let (width, height) = self.target.size();
+        render_pass.set_scissor_rect(0, 0, width.max(1), height.max(1));
+    }
+
+    /// Pixels a layer is composited into, `None` if it's clipped away entirely.
+    fn composite_rect(&self, layer: u32) -> Option<[u32; 4]> {
+        let (width, height) = self.target.size();
+        let size = (width.max(1), height.max(1));
+
+        match self.layer_plan.layers[layer as usize].clip {
+            Some(clip) => scissor_rect(clip, self.scale, size),
+            None => Some([0, 0, size.0, size.1]),
+        }
+    }
+
+    /// Creates the pipeline compositing with `blend_mode` into `format` unless an earlier frame
+    /// did.
+    fn ensure_composite_pipeline(&mut self, blend_mode: BlendMode, format: wgpu::TextureFormat) {
+        let blend_state = blend_mode.blend_state();
+        if self
+            .composite_pipelines
+            .contains_key(&(blend_state, format))
+        {
+            return;
+        }
+
+        // the shader writes the blended result over the backdrop it read
+        let (label, entry_point, bind_group_layouts) = match blend_state {
+            Some(_) => (
+                "Composite Pipeline",
+                "fs_main",
+                vec![&self.layer_bind_group_layout],
+            ),
+            None => (
+                "Blend Pipeline",
+                "fs_blend",
+                vec![
+                    &self.layer_bind_group_layout,
+                    &self.backdrop_bind_group_layout,
+                ],
+            ),
+        };
+
+        let layout = self
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Composite Pipeline Layout"),
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &[],
+            });
+
+        let pipeline = self
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &self.composite_shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &self.composite_shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(blend_state.unwrap_or(wgpu::BlendState::REPLACE)),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: self.sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            });
+
+        self.composite_pipelines
+            .insert((blend_state, format), pipeline);
+    }
+
+    /// Plans which draw calls go into which layer and takes textures for the layers from the
+    /// pool.
+    ///
+    /// With `one_root_pass` the children of the target are composited in a single pass, as
+    /// `paint` does, so they can't share textures.
+    fn prepare_layers(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        draw_calls: &[DrawCall],
+        layers: &[Layer],
+        one_root_pass: bool,
+    ) -> Result<()> {
+        let (width, height) = self.target.size();
+        let size = (width.max(1), height.max(1));
+        // targets storing colors as written would blend the encoded colors, so what is drawn
+        // into them goes into an sRGB layer that is encoded when composited
+        let encode_root = self.target.layer_format() != self.target.format();
+        let mut plan = LayerPlan::new(draw_calls, layers, encode_root);
+
+        // the root layer starts with the clear color, which replaces the target's content
+        if let (Some(root_layer), Some(_)) = (plan.root_layer, self.clear_color) {
+            plan.layers[root_layer as usize].blend_mode = BlendMode::Copy;
+        }
+
+        let texture_count = plan.assign_textures(one_root_pass);
+        self.layer_pool.acquire(
+            device,
+            &self.layer_bind_group_layout,
+            self.target.layer_format(),
+            self.sample_count,
+            size,
+            texture_count,
+        );
+
+        for layer in plan.order.iter().filter(|layer| **layer != 0) {
+            let Layer {
+                parent, blend_mode, ..
+            } = plan.layers[*layer as usize];
+            let format = match parent {
+                0 => self.target.format(),
+                _ => self.target.layer_format(),
+            };
+            self.ensure_composite_pipeline(blend_mode, format);
+            if blend_mode.needs_backdrop() {
+                self.layer_pool.acquire_backdrop(
+                    device,
+                    &self.backdrop_bind_group_layout,
+                    self.target.layer_format(),
+                    size,
+                );
+            }
+        }
+
+        let uniforms = plan
+            .layers
+            .iter()
+            .enumerate()
+            .map(|(index, layer)| LayerUniform {
+                opacity: layer.opacity,
+                blend_mode: layer.blend_mode.shader_mode(),
+                encode_srgb: (encode_root && plan.root_layer == Some(index as u32)) as u32,
+                _pad: 0,
+            })
+            .collect::<Vec<_>>();
+        let written = self.layer_pool.write_uniforms(
+            device,
+            &mut self.staging_belt,
+            encoder,
+            &self.layer_bind_group_layout,
+            &uniforms,
+        );
+
+        let filters = self.prepare_filters(device, encoder, &plan, size);
+        self.layer_plan = plan;
+
+        written.and(filters)
+    }
+
+    /// Uploads the parameters of the filter passes of the layers and binds their textures.
+    fn prepare_filters(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        plan: &LayerPlan,
+        size: (u32, u32),
+    ) -> Result<()> {
+        self.filter_chains.clear();
+
+        // filters work on linear colors whatever the target stores
+        let color = |color: &piet::Color| {
+            let (r, g, b, a) = color.as_rgba();
+            let linear = |channel: f64| srgb_to_linear(channel) as f32;
+            [linear(r), linear(g), linear(b), a as f32]
+        };
+        let decode_srgb = !self.target.layers_encode_srgb() as u32;
+
+        // each pass binds its own part of the uniform buffer
+        let uniform_size = std::mem::size_of::<FilterUniform>() as u64;
+        let stride = wgpu::util::align_to(
+            uniform_size,
+            device.limits().min_uniform_buffer_offset_alignment as u64,
+        );
+        let mut uniforms = Vec::new();
+        let mut chains = Vec::new();
+
+        for layer in &plan.order {
+            let Some(texture) = plan.textures[*layer as usize] else {
+                continue;
+            };
+            let filters = &plan.layers[*layer as usize].filters;
+            if filters.is_empty() {
+                continue;
+            }
+
+            let (steps, result) = filter_steps(filters, self.scale, color);
+            for step in &steps {
+                let uniform = FilterUniform {
+                    decode_srgb,
+                    ..step.uniform
+                };
+                uniforms.extend_from_slice(bytemuck::bytes_of(&uniform));
+                uniforms.resize(
+                    wgpu::util::align_to(uniforms.len() as u64, stride) as usize,
+                    0,
+                );
+            }
+            chains.push((*layer, texture, steps, result));
+        }
+
+        if chains.is_empty() {
+            return Ok(());
+        }
+
+        self.filter_pool
+            .acquire(device, self.target.layer_format(), size);
+        self.filter_pool
+            .uniforms
+            .write(device, &mut self.staging_belt, encoder, &uniforms)?;
+
+        let mut offset = 0;
+        for (layer, texture, steps, result) in chains {
+            let layer_view = &self.layer_pool.get(texture).view;
+            let view = |slot| match slot {
+                Slot::Layer => layer_view,
+                Slot::Scratch(index) => &self.filter_pool.scratch(index).view,
+            };
+
+            let passes = steps
+                .iter()
+                .map(|step| {
+                    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: Some("Filter Bind Group"),
+                        layout: &self.filter_bind_group_layout,
+                        entries: &[
+                            wgpu::BindGroupEntry {
+                                binding: 0,
+                                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                                    buffer: self.filter_pool.uniforms.buffer(),
+                                    offset,
+                                    size: NonZeroU64::new(uniform_size),
+                                }),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 1,
+                                resource: wgpu::BindingResource::TextureView(view(step.source)),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 2,
+                                resource: wgpu::BindingResource::TextureView(view(step.original)),
+                            },
+                        ],
+                    });
+                    offset += stride;
+
+                    FilterPass {
+                        kind: step.kind,
+                        bind_group,
+                        target: step.target,
+                    }
+                })
+                .collect();
+
+            self.filter_chains
+                .insert(layer, FilterChain { passes, result });
+        }
+
+        let kinds = self
+            .filter_chains
+            .values()
+            .flat_map(|chain| chain.passes.iter().map(|pass| pass.kind))
+            .collect::<Vec<_>>();
+        for kind in kinds {
+            self.ensure_filter_pipeline(kind);
+        }
+
+        Ok(())
+    }
+
+    /// Creates the pipeline of a filter pass unless an earlier frame did.
+    fn ensure_filter_pipeline(&mut self, kind: FilterKind) {
+        if self.filter_pipelines.contains_key(&kind) {
+            return;
+        }
+
+        let layout = self
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Filter Pipeline Layout"),
+                bind_group_layouts: &[&self.filter_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        // the scratch textures and resolved layers aren't multisampled
+        let pipeline = self
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Filter Pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &self.filter_shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &self.filter_shader,
+                    entry_point: kind.entry_point(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: self.target.layer_format(),
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            });
+
+        self.filter_pipelines.insert(kind, pipeline);
+    }
+
+    /// Renders the children of the target into their textures, for `paint` to composite them
+    /// all in the pass of the caller.
+    fn draw_layers(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        index_format: wgpu::IndexFormat,
+        draw_calls: &[DrawCall],
+    ) {
+        for child in self.layer_plan.children(0) {
+            self.draw_layer(encoder, index_format, draw_calls, child);
+        }
+    }
+
+    /// Renders `layer` into its texture, each child right before it's composited, so the next
+    /// child can use its texture.
+    ///
+    /// A pass of the layer ends before each child, children blended with the backdrop get the
+    /// part they are composited into copied to the backdrop texture before the next pass.
+    fn draw_layer(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        index_format: wgpu::IndexFormat,
+        draw_calls: &[DrawCall],
+        layer: u32,
+    ) {
+        let Some(texture) = self.layer_plan.textures[layer as usize] else {
+            return;
+        };
+        let texture = self.layer_pool.get(texture);
+        let steps = &self.layer_plan.steps[layer as usize];
+
+        let mut load = match (Some(layer) == self.layer_plan.root_layer, self.clear_color) {
+            (true, Some(color)) => {
+                wgpu::LoadOp::Clear(self.clear_value(color, self.target.layer_format()))
+            }
+            _ => wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+        };
+
+        // every pass but the first starts with a child, a layer starting with one gets an empty
+        // pass clearing it first
+        for (index, range) in self.passes(steps).into_iter().enumerate() {
+            if let (true, Step::Composite(child)) = (index > 0, &steps[range.start]) {
+                self.draw_layer(encoder, index_format, draw_calls, *child);
+                if self.reads_backdrop(&steps[range.start]) {
+                    self.copy_backdrop(encoder, &texture.texture, *child);
+                }
+            }
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Layer Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: texture.multisample_view.as_ref().unwrap_or(&texture.view),
+                    resolve_target: texture.multisample_view.as_ref().map(|_| &texture.view),
+                    ops: wgpu::Operations { load, store: true },
+                })],
+                depth_stencil_attachment: None,
+            });
+
+            self.paint_steps(&mut render_pass, index_format, draw_calls, &steps[range]);
+            load = wgpu::LoadOp::Load;
+        }
+
+        if let Some(chain) = self.filter_chains.get(&layer) {
+            self.draw_filters(encoder, texture, chain);
+        }
+    }
+
+    /// Steps drawn in one pass each, a pass ends before each child that is rendered.
+    fn passes(&self, steps: &[Step]) -> Vec<Range<usize>> {
+        let mut starts = vec![0];
+        starts.extend((0..steps.len()).filter(|index| match &steps[*index] {
+            Step::Composite(child) => self.layer_plan.textures[*child as usize].is_some(),
+            Step::Draw(_) => false,
+        }));
+        let ends = starts[1..].iter().copied().chain([steps.len()]);
+
+        starts
+            .iter()
+            .copied()
+            .zip(ends)
+            .map(|(start, end)| start..end)
+            .collect()
+    }
+
+    /// Runs the filter passes of a finished layer and leaves the result in its texture.
+    fn draw_filters(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &LayerTexture,
+        chain: &FilterChain,
+    ) {
+        let view = |slot| match slot {
+            Slot::Layer => &texture.view,
+            Slot::Scratch(index) => &self.filter_pool.scratch(index).view,
+        };
+
+        for pass in &chain.passes {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Filter Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: view(pass.target),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_pipeline(&self.filter_pipelines[&pass.kind]);
+            render_pass.set_bind_group(0, &pass.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        if let Slot::Scratch(index) = chain.result {
+            let (width, height) = texture.size;
+            encoder.copy_texture_to_texture(
+                self.filter_pool.scratch(index).texture.as_image_copy(),
+                texture.texture.as_image_copy(),
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
+
+    fn reads_backdrop(&self, step: &Step) -> bool {
+        match step {
+            Step::Composite(child) => {
+                self.layer_plan.layers[*child as usize]
+                    .blend_mode
+                    .needs_backdrop()
+                    && self.layer_plan.textures[*child as usize].is_some()
+            }
+            Step::Draw(_) => false,
+        }
+    }
+
+    /// Copies what `layer` is composited into from its parent's texture to the backdrop.
+    fn copy_backdrop(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        parent: &wgpu::Texture,
+        layer: u32,
+    ) {
+        let (Some(backdrop), Some([x, y, width, height])) =
+            (self.layer_pool.backdrop(), self.composite_rect(layer))
+        else {
+            return;
+        };
+        let origin = wgpu::Origin3d { x, y, z: 0 };
+
+        encoder.copy_texture_to_texture(
+            wgpu::ImageCopyTexture {
+                texture: parent,
+                mip_level: 0,
+                origin,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyTexture {
+                texture: &backdrop.texture,
+                mip_level: 0,
+                origin,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Clear colors are written as they are, so they get the treatment of the shader's output
+    /// for a texture of `format`.
+    fn clear_value(&self, color: wgpu::Color, format: wgpu::TextureFormat) -> wgpu::Color {
+        let encode = |channel: f64| match format.describe().srgb {
+            true => srgb_to_linear(channel),
+            false => channel,
+        };
+        let alpha = match self.target.premultiplied_alpha() {
+            true => color.a,
+            false => 1.0,
+        };
+
+        wgpu::Color {
+            r: encode(color.r) * alpha,
+            g: encode(color.g) * alpha,
+            b: encode(color.b) * alpha,
+            a: color.a,
+        }
+    }
+
+    /// Renders the frame into `view`, with the layers rendered right before they are
+    /// composited.
+    fn draw_frame(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        index_format: wgpu::IndexFormat,
+        draw_calls: &[DrawCall],
+    ) {
+        let mut load = match self.clear_color {
+            Some(color) => wgpu::LoadOp::Clear(self.clear_value(color, self.target.format())),
+            None => wgpu::LoadOp::Load,
+        };
+        // layers blended with the backdrop of the target are in the plan's root layer
+        let steps = self.layer_plan.steps.first().map_or(&[][..], Vec::as_slice);
+
+        for (index, range) in self.passes(steps).into_iter().enumerate() {
+            // every pass but the first starts with a child, rendered right before
+            if let (true, Step::Composite(child)) = (index > 0, &steps[range.start]) {
+                self.draw_layer(encoder, index_format, draw_calls, *child);
+            }
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: self.multisample_view.as_ref().unwrap_or(view),
+                    resolve_target: self.multisample_view.as_ref().map(|_| view),
+                    ops: wgpu::Operations { load, store: true },
+                })],
+                depth_stencil_attachment: None,
+            });
+
+            self.paint_steps(&mut render_pass, index_format, draw_calls, &steps[range]);
+            load = wgpu::LoadOp::Load;
+        }
+    }
+
+    /// Renders the draw calls of the last frame again into a texture and reads it back, the
+    /// buffers still hold the frame until the next one is uploaded.
+    ///
+    /// Frames drawn on top of a view of the caller are drawn on transparent instead.
+    pub fn screenshot(
+        &mut self,
+        index_format: wgpu::IndexFormat,
+        draw_calls: &[DrawCall],
+    ) -> Result<RgbaImage> {
+        let (width, height) = self.target.size();
+        let (width, height) = (width.max(1), height.max(1));
+        let format = self.target.format();
+
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Screenshot Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Screenshot Encoder"),
+            });
+        self.draw_frame(&mut encoder, &view, index_format, draw_calls);
+
+        let image = screenshot::read_texture(
+            &self.device,
+            &self.queue,
+            encoder,
+            &texture,
+            (width, height),
+            format,
+            self.target.premultiplied_alpha(),
+        );
+
+        self.check_errors()?;
+        image
+    }
+
+    /// Renders the draw calls into the target with the uploads recorded into `encoder`, submits
+    /// everything and presents the frame.
+    ///
+    /// Frames the target has no texture for are skipped, the uploads are submitted anyway. If
+    /// the surface is lost, the gpu resources are recreated and the frame fails with
+    /// `FrameDropped`, so it can be drawn again.
+    pub fn render(
+        &mut self,
+        mut encoder: wgpu::CommandEncoder,
+        index_format: wgpu::IndexFormat,
+        draw_calls: &[DrawCall],
+        layers: &[Layer],
+    ) -> Result<()> {
+        let frame = match self.target.next_frame(&self.device) {
+            Ok(frame) => frame,
+            // reconfiguring didn't help, so the device is gone as well
+            Err(PietWgpuError::Surface(wgpu::SurfaceError::Lost)) => {
+                self.rebuild()?;
+                return Err(PietWgpuError::FrameDropped);
+            }
+            Err(error) => return Err(error),
+        };
+
+        let device = self.device.clone();
+        self.write_globals(&device, &mut encoder);
+        let prepared = self.prepare_layers(&device, &mut encoder, draw_calls, layers, false);
+
+        if let (Ok(()), Some(frame)) = (&prepared, &frame) {
+            self.draw_frame(&mut encoder, &frame.view, index_format, draw_calls);
+        }
+
+        self.staging_belt.finish();
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.staging_belt.recall();
+
+        if let Some(frame) = frame {
+            frame.present();
+        }
+
+        prepared?;
+        self.check_errors()
+    }
+}
+
+/// Clamps the configured sample count to one wgpu supports, which is 1 or 4 for render passes.
+/// Multisampling is disabled if the target format can't be multisampled and resolved.
+fn sample_count(
+    adapter: Option<&wgpu::Adapter>,
+    format: wgpu::TextureFormat,
+    requested: u32,
+) -> u32 {
+    let flags = match adapter {
+        Some(adapter) => adapter.get_texture_format_features(format).flags,
+        None => format.describe().guaranteed_format_features.flags,
+    };
+
+    let sample_count = match requested {
+        0 | 1 => 1,
+        _ => 4,
+    };
+
+    if sample_count != requested {
+        log::warn!("Sample count {requested} isn't supported, using {sample_count}");
+    }
+
+    let multisample = wgpu::TextureFormatFeatureFlags::MULTISAMPLE
+        | wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE;
+    if sample_count > 1 && !flags.contains(multisample) {
+        log::warn!("{format:?} doesn't support multisampling, rendering without it");
+        return 1;
+    }
+
+    sample_count
+}
+
+fn create_multisample_view(
+    device: &wgpu::Device,
+    target: &RenderTarget,
+    sample_count: u32,
+) -> Option<wgpu::TextureView> {
+    if sample_count == 1 {
+        return None;
+    }
+
+    let (width, height) = target.size();
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Multisample Texture"),
+        size: wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: target.format(),
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+    });
+
+    Some(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+}
+
+/// An image in the texture buffer along with the copy it is restored from.
+struct AtlasEntry {
+    rect: Rect,
+    image: Arc<RgbaImage>,
+}
+
+impl AtlasEntry {
+    /// Copies the image data to its place in the texture buffer.
+    fn upload(
+        &self,
+        device: &wgpu::Device,
+        staging_belt: &mut StagingBelt,
+        encoder: &mut wgpu::CommandEncoder,
+        texture_buffer: &wgpu::Texture,
+    ) {
+        staging_belt.write_texture(
+            device,
+            encoder,
+            texture_buffer,
+            wgpu::Origin3d {
+                x: self.rect.x0 as u32,
+                y: self.rect.y0 as u32,
+                z: 0,
+            },
+            wgpu::Extent3d {
+                width: self.image.width(),
+                height: self.image.height(),
+                depth_or_array_layers: 1,
+            },
+            &self.image,
+        );
+    }
+}
+
+/// Message of wgpu-core's `DeviceError::Lost` as of wgpu 0.14, check it when updating wgpu.
+///
+/// wgpu 0.14 has no device lost callback and boxes wgpu-core's errors, which it doesn't export,
+/// so device loss can only be told apart from other errors by this message.
+const DEVICE_LOST_MESSAGE: &str = "parent device is lost";
+
+/// wgpu only reports device loss as the source of other errors.
+fn is_device_lost(error: &wgpu::Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+
+    while let Some(error) = source {
+        if error.to_string() == DEVICE_LOST_MESSAGE {
+            return true;
+        }
+
+        source = error.source();
+    }
+
+    false
+}
+
+fn srgb_to_linear(channel: f64) -> f64 {
+    match channel <= 0.04045 {
+        true => channel / 12.92,
+        false => ((channel + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+fn create_prim_bind_group(
+    device: &wgpu::Device,
+    layout: &BindGroupLayout,
+    prim_buffer: &GrowableBuffer,
+    prim_binding: PrimitiveBinding,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+        label: Some("Primitives Bind Group"),
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer: prim_buffer.buffer(),
+                offset: 0,
+                size: prim_binding.binding_size(prim_buffer.size(), &device.limits()),
+            }),
+        }],
+    })
+}
+
+/// Range of indices drawn with the primitives bound at `prim_offset`.
+#[derive(Clone)]
+pub struct DrawCall {
+    pub indices: Range<u32>,
+    pub prim_offset: u32,
+    /// Drawn with the pipeline replacing the target's pixels.
+    pub replace: bool,
+    /// Layer the draw call draws into.
+    pub layer: u32,
+}
+
+/// Adds the index at `position` to the last draw call, or starts a new one if the index refers
+/// to a primitive in another chunk of the uniform fallback, one composited the other way or one
+/// in another layer.
+pub fn push_index(
+    draw_calls: &mut Vec<DrawCall>,
+    position: u32,
+    primitive: (u32, &Primitive),
+    prim_binding: PrimitiveBinding,
+) {
+    let (prim_index, primitive) = primitive;
+    let prim_offset = match prim_binding {
+        PrimitiveBinding::Storage => 0,
+        PrimitiveBinding::Uniform { chunk_size } => {
+            prim_index / chunk_size * chunk_size * std::mem::size_of::<Primitive>() as u32
+        }
+    };
+    let replace = primitive.replace != 0;
+    let layer = primitive.layer;
+
+    match draw_calls.last_mut() {
+        Some(draw_call)
+            if draw_call.prim_offset == prim_offset
+                && draw_call.replace == replace
+                && draw_call.layer == layer =>
+        {
+            draw_call.indices.end = position + 1
+        }
+        _ => draw_calls.push(DrawCall {
+            indices: position..position + 1,
+            prim_offset,
+            replace,
+            layer,
+        }),
+    }
+}
+
+/// Appends `draw_call`, merging it into the last one if it continues its indices.
+pub fn push_draw_call(draw_calls: &mut Vec<DrawCall>, draw_call: &DrawCall) {
+    match draw_calls.last_mut() {
+        Some(last)
+            if last.indices.end == draw_call.indices.start
+                && last.prim_offset == draw_call.prim_offset
+                && last.replace == draw_call.replace
+                && last.layer == draw_call.layer =>
+        {
+            last.indices.end = draw_call.indices.end
+        }
+        _ => draw_calls.push(draw_call.clone()),
+    }
+}
+
+/// Primitives are indexed relative to the chunk bound for their draw call in the uniform
+/// fallback.
+pub fn chunk_relative(vertices: &[Vertex], prim_binding: PrimitiveBinding) -> Cow<'_, [Vertex]> {
+    match prim_binding {
+        PrimitiveBinding::Storage => Cow::Borrowed(vertices),
+        PrimitiveBinding::Uniform { chunk_size } => Cow::Owned(
+            vertices
+                .iter()
+                .map(|vertex| Vertex {
+                    prim_index: vertex.prim_index % chunk_size,
+                    ..*vertex
+                })
+                .collect(),
+        ),
+    }
+}
@@ -1,23 +1,53 @@
-use image::{DynamicImage, GenericImageView, RgbaImage};
-use kurbo::{Rect, Size, Vec2};
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use image::RgbaImage;
+use kurbo::Size;
 
 use crate::error::Result;
 
+static NEXT_IMAGE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Clones share the id of the original, so they are uploaded to the image atlas only once.
 #[derive(Clone)]
 pub struct WgpuImage {
-    dynamic: DynamicImage,
+    pub(crate) id: u64,
+    // shared with the texture buffer, which keeps a copy to restore it after device loss
+    pub(crate) buffer: Arc<RgbaImage>,
 }
 
 impl WgpuImage {
-    pub fn from_bytes(bytes: &[u8]) -> Self {
-        let image = image::load_from_memory(bytes).unwrap();
-        Self { dynamic: image }
+    /// Decodes an encoded image, the format is guessed from its content.
+    ///
+    /// Images of any color type are converted to RGBA8 on load. Formats other than png and jpeg
+    /// need the `gif`, `webp`, `bmp` or `tiff` feature (or all of them via `extra-codecs`).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let image = image::load_from_memory(bytes)?;
+        Ok(Self::from_rgba(image.to_rgba8()))
+    }
+
+    /// Opens and decodes an image file, the format is guessed from the file extension.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let image = image::open(path)?;
+        Ok(Self::from_rgba(image.to_rgba8()))
+    }
+
+    pub fn from_rgba(buffer: RgbaImage) -> Self {
+        Self {
+            id: NEXT_IMAGE_ID.fetch_add(1, Ordering::Relaxed),
+            buffer: Arc::new(buffer),
+        }
     }
 }
 
 impl piet::Image for WgpuImage {
     fn size(&self) -> Size {
-        let (width, height) = self.dynamic.dimensions();
+        let (width, height) = self.buffer.dimensions();
 
         Size {
             width: width.into(),
@@ -25,22 +55,3 @@ impl piet::Image for WgpuImage {
         }
     }
 }
-
-struct WgpuImageAtlas {
-    size: Vec2,
-    images: Vec<Rect>,
-    buffer: RgbaImage,
-}
-
-impl WgpuImageAtlas {
-    fn new(size: Vec2) -> Self {
-        Self {
-            size,
-            buffer: RgbaImage::new(size.x as u32, size.y as u32),
-        }
-    }
-
-    fn push(&mut self, image: WgpuImage) -> Result<Rect> {
-        self.images
-    }
-}
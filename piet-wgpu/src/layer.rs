@@ -0,0 +1,742 @@
+use std::{num::NonZeroU64, ops::Range};
+
+use piet::{
+    kurbo::{Affine, Rect},
+    Error, RenderContext,
+};
+
+use crate::{
+    blend::BlendMode,
+    buffer::GrowableBuffer,
+    error::{self, PietWgpuError},
+    filter::Filter,
+    gpu::DrawCall,
+    renderer::WgpuRenderer,
+    staging::StagingBelt,
+    PietWgpu,
+};
+
+/// Drawing into layers that are composited as a whole, which piet itself doesn't provide.
+pub trait RenderContextExt: RenderContext {
+    /// Draws everything up to the matching `pop_layer` into a layer of its own, which is then
+    /// composited with `opacity` and `blend_mode`, only inside of `clip` if given.
+    ///
+    /// Layers nest, a layer still open when the frame is finished is composited as if it was
+    /// popped.
+    fn push_layer(&mut self, opacity: f64, blend_mode: BlendMode, clip: impl Into<Option<Rect>>) {
+        self.push_filtered_layer(&[], opacity, blend_mode, clip);
+    }
+
+    /// Like `push_layer`, with `filters` applied to the layer in order before it's composited.
+    ///
+    /// The clip is applied after the filters, so blurs and shadows can reach out of the layer's
+    /// content up to it.
+    fn push_filtered_layer(
+        &mut self,
+        filters: &[Filter],
+        opacity: f64,
+        blend_mode: BlendMode,
+        clip: impl Into<Option<Rect>>,
+    );
+
+    /// Ends the layer pushed last, fails if there is none.
+    fn pop_layer(&mut self) -> Result<(), Error>;
+}
+
+impl<T: WgpuRenderer> RenderContextExt for PietWgpu<T> {
+    fn push_filtered_layer(
+        &mut self,
+        filters: &[Filter],
+        opacity: f64,
+        blend_mode: BlendMode,
+        clip: impl Into<Option<Rect>>,
+    ) {
+        self.ensure_frame();
+        let result = self.renderer.push_layer(Layer {
+            parent: 0,
+            opacity: opacity.clamp(0.0, 1.0) as f32,
+            blend_mode,
+            clip: clip.into(),
+            filters: filters.to_vec(),
+        });
+        self.record_error(result);
+    }
+
+    fn pop_layer(&mut self) -> Result<(), Error> {
+        self.ensure_frame();
+        self.renderer.pop_layer().map_err(|error| match error {
+            PietWgpuError::NoOpenLayer => Error::StackUnbalance,
+            error => error.into(),
+        })
+    }
+}
+
+/// A layer of a frame, primitives refer to it by its index. Index 0 is the target itself.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    pub parent: u32,
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+    pub clip: Option<Rect>,
+    // applied in order once the layer is drawn
+    pub filters: Vec<Filter>,
+}
+
+impl Layer {
+    pub const ROOT: Self = Layer {
+        parent: 0,
+        opacity: 1.0,
+        blend_mode: BlendMode::SourceOver,
+        clip: None,
+        filters: Vec::new(),
+    };
+}
+
+/// Layers of the frame being drawn and the ones open at the moment.
+#[derive(Clone, Debug)]
+pub struct LayerStack {
+    pub layers: Vec<Layer>,
+    open: Vec<u32>,
+}
+
+impl LayerStack {
+    pub fn new() -> Self {
+        Self {
+            layers: vec![Layer::ROOT],
+            open: Vec::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.layers.truncate(1);
+        self.open.clear();
+    }
+
+    /// The layer drawn into at the moment.
+    pub fn current(&self) -> u32 {
+        self.open.last().copied().unwrap_or(0)
+    }
+
+    pub fn push(&mut self, layer: Layer) {
+        self.layers.push(Layer {
+            parent: self.current(),
+            ..layer
+        });
+        self.open.push(self.layers.len() as u32 - 1);
+    }
+
+    /// Returns whether there was a layer to pop.
+    pub fn pop(&mut self) -> bool {
+        self.open.pop().is_some()
+    }
+}
+
+impl Default for LayerStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A part of what is drawn into a layer.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    /// Draw calls in this range.
+    Draw(Range<usize>),
+    /// The finished child layer.
+    Composite(u32),
+}
+
+/// What goes into each layer of a frame, derived from the layers the draw calls refer to.
+#[derive(Clone, Debug, Default)]
+pub struct LayerPlan {
+    /// Steps by layer.
+    pub steps: Vec<Vec<Step>>,
+    /// Layers with content in the order they are rendered in, children before their parents.
+    /// The root comes last.
+    pub order: Vec<u32>,
+    pub layers: Vec<Layer>,
+    /// Index of the pooled texture by layer, set for layers that are rendered. Layers share a
+    /// texture once the one before is composited, see `assign_textures`.
+    pub textures: Vec<Option<usize>>,
+    /// Layer taking what is drawn into the target itself if layers are blended into it with the
+    /// backdrop, which can't be read from the target, or if the target can't blend in linear
+    /// light.
+    pub root_layer: Option<u32>,
+}
+
+impl LayerPlan {
+    /// With `own_root` the target's content always gets a root layer.
+    pub fn new(draw_calls: &[DrawCall], layers: &[Layer], own_root: bool) -> Self {
+        let mut layers = match layers.is_empty() {
+            true => vec![Layer::ROOT],
+            false => layers.to_vec(),
+        };
+
+        // everything in the target itself moves into a layer of its own, composited last
+        let blended_with_target = layers[1..]
+            .iter()
+            .any(|layer| layer.parent == 0 && layer.blend_mode.needs_backdrop());
+        let root_layer = (own_root || blended_with_target).then(|| {
+            let root_layer = layers.len() as u32;
+            for layer in &mut layers[1..] {
+                if layer.parent == 0 {
+                    layer.parent = root_layer;
+                }
+            }
+            layers.push(Layer::ROOT);
+            root_layer
+        });
+
+        let mut plan = LayerPlan {
+            steps: vec![Vec::new(); layers.len()],
+            order: Vec::new(),
+            textures: vec![None; layers.len()],
+            layers,
+            root_layer,
+        };
+        let mut open = vec![0];
+
+        for (position, draw_call) in draw_calls.iter().enumerate() {
+            let draw_layer = match draw_call.layer {
+                0 => root_layer.unwrap_or(0),
+                layer => layer,
+            };
+
+            // layers not containing the draw call are done
+            while !plan.is_within(draw_layer, *open.last().unwrap()) {
+                plan.close(open.pop().unwrap());
+            }
+
+            // the draw call may be nested in layers without draw calls of their own
+            let mut nested = Vec::new();
+            let mut layer = draw_layer;
+            while layer != *open.last().unwrap() {
+                nested.push(layer);
+                layer = plan.layers[layer as usize].parent;
+            }
+            open.extend(nested.into_iter().rev());
+
+            let steps = &mut plan.steps[draw_layer as usize];
+            match steps.last_mut() {
+                Some(Step::Draw(range)) if range.end == position => range.end = position + 1,
+                _ => steps.push(Step::Draw(position..position + 1)),
+            }
+        }
+
+        while let Some(layer) = open.pop() {
+            plan.close(layer);
+        }
+
+        plan
+    }
+
+    /// Assigns the pooled textures to the layers and returns how many the frame needs.
+    ///
+    /// Each layer is rendered right before its parent composites it, after which its texture is
+    /// free for the next one. That makes the number of textures the depth of the nesting, unless
+    /// the target composites all its children in `one_root_pass`, then they keep theirs.
+    pub fn assign_textures(&mut self, one_root_pass: bool) -> usize {
+        let mut free = Vec::new();
+        let mut count = 0;
+        self.assign_children(0, one_root_pass, &mut free, &mut count);
+
+        count
+    }
+
+    fn assign_children(
+        &mut self,
+        layer: u32,
+        keep: bool,
+        free: &mut Vec<usize>,
+        count: &mut usize,
+    ) {
+        for child in self.children(layer).collect::<Vec<_>>() {
+            let texture = free.pop().unwrap_or_else(|| {
+                *count += 1;
+                *count - 1
+            });
+            self.textures[child as usize] = Some(texture);
+            self.assign_children(child, false, free, count);
+
+            if !keep {
+                free.push(texture);
+            }
+        }
+    }
+
+    /// Children of `layer` in the order it composites them.
+    pub fn children(&self, layer: u32) -> impl Iterator<Item = u32> + '_ {
+        self.steps[layer as usize]
+            .iter()
+            .filter_map(|step| match step {
+                Step::Composite(child) => Some(*child),
+                Step::Draw(_) => None,
+            })
+    }
+
+    fn close(&mut self, layer: u32) {
+        self.order.push(layer);
+        if layer != 0 {
+            let parent = self.layers[layer as usize].parent;
+            self.steps[parent as usize].push(Step::Composite(layer));
+        }
+    }
+
+    /// Whether `layer` is `ancestor` or nested in it.
+    fn is_within(&self, mut layer: u32, ancestor: u32) -> bool {
+        loop {
+            if layer == ancestor {
+                return true;
+            }
+            if layer == 0 {
+                return false;
+            }
+            layer = self.layers[layer as usize].parent;
+        }
+    }
+}
+
+/// Layers parameters the composite shader reads.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LayerUniform {
+    pub opacity: f32,
+    pub blend_mode: u32,
+    /// Set for the root layer of targets storing colors as written.
+    pub encode_srgb: u32,
+    pub _pad: u32,
+}
+
+/// A texture a layer is rendered into and composited from.
+pub struct LayerTexture {
+    pub size: (u32, u32),
+    // copied from for layers blended with the backdrop, and to by filters
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    // rendered into and resolved to `view` if multisampling is enabled
+    pub multisample_view: Option<wgpu::TextureView>,
+    // the texture and the layer uniforms, bound at the offset of the layer using the texture
+    pub bind_group: wgpu::BindGroup,
+}
+
+/// Layer textures kept from frame to frame, so layers don't allocate once the frames need the
+/// same number of them.
+pub struct LayerPool {
+    textures: Vec<LayerTexture>,
+    // uniforms of every layer of the frame by layer index, textures are shared between layers
+    uniforms: GrowableBuffer,
+    uniform_stride: u64,
+    // one copy at a time is enough, the copies are ordered with the passes reading them
+    backdrop: Option<Backdrop>,
+    allocations: u64,
+}
+
+/// Copy of the pixels a layer is blended with by the composite shader.
+pub struct Backdrop {
+    size: (u32, u32),
+    pub texture: wgpu::Texture,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl LayerPool {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let uniform_stride = wgpu::util::align_to(
+            std::mem::size_of::<LayerUniform>() as u64,
+            device.limits().min_uniform_buffer_offset_alignment as u64,
+        );
+
+        Self {
+            textures: Vec::new(),
+            // large enough for the bind groups of textures created before the first upload
+            uniforms: GrowableBuffer::new(
+                device,
+                "Layer Uniform Buffer",
+                wgpu::BufferUsages::UNIFORM,
+                uniform_stride,
+            ),
+            uniform_stride,
+            backdrop: None,
+            allocations: 0,
+        }
+    }
+
+    /// Makes sure there are `count` textures as large as the target, reusing those of earlier
+    /// frames, and drops the rest.
+    pub fn acquire(
+        &mut self,
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        size: (u32, u32),
+        count: usize,
+    ) {
+        self.textures.truncate(count);
+
+        for index in 0..count {
+            if self.textures.get(index).map(|texture| texture.size) == Some(size) {
+                continue;
+            }
+
+            let texture = LayerTexture::new(
+                device,
+                bind_group_layout,
+                self.uniforms.buffer(),
+                format,
+                sample_count,
+                size,
+            );
+            self.allocations += 1;
+
+            match index < self.textures.len() {
+                true => self.textures[index] = texture,
+                false => self.textures.push(texture),
+            }
+        }
+    }
+
+    /// Uploads the uniforms of the frame's layers, indexed like the layers.
+    pub fn write_uniforms(
+        &mut self,
+        device: &wgpu::Device,
+        staging_belt: &mut StagingBelt,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        uniforms: &[LayerUniform],
+    ) -> error::Result<()> {
+        let mut data = vec![0; uniforms.len() * self.uniform_stride as usize];
+        for (uniform, slot) in uniforms
+            .iter()
+            .zip(data.chunks_mut(self.uniform_stride as usize))
+        {
+            slot[..std::mem::size_of::<LayerUniform>()]
+                .copy_from_slice(bytemuck::bytes_of(uniform));
+        }
+
+        // the bind groups refer to the buffer that was replaced
+        if self.uniforms.write(device, staging_belt, encoder, &data)? {
+            for texture in &mut self.textures {
+                texture.bind_group = LayerTexture::create_bind_group(
+                    device,
+                    bind_group_layout,
+                    self.uniforms.buffer(),
+                    &texture.view,
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Dynamic offset of the uniform of `layer` in the bind groups of the textures.
+    pub fn uniform_offset(&self, layer: u32) -> u32 {
+        (layer as u64 * self.uniform_stride) as u32
+    }
+
+    pub fn get(&self, index: usize) -> &LayerTexture {
+        &self.textures[index]
+    }
+
+    /// Makes sure there is a backdrop texture as large as the target.
+    pub fn acquire_backdrop(
+        &mut self,
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        size: (u32, u32),
+    ) {
+        if self.backdrop.as_ref().map(|backdrop| backdrop.size) != Some(size) {
+            self.backdrop = Some(Backdrop::new(device, bind_group_layout, format, size));
+            self.allocations += 1;
+        }
+    }
+
+    /// The backdrop texture, if a frame needed one.
+    pub fn backdrop(&self) -> Option<&Backdrop> {
+        self.backdrop.as_ref()
+    }
+
+    /// Number of layer textures and uniform buffers created so far, including replaced ones.
+    pub fn allocations(&self) -> u64 {
+        self.allocations + self.uniforms.allocations()
+    }
+}
+
+impl LayerTexture {
+    fn new(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        uniforms: &wgpu::Buffer,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        (width, height): (u32, u32),
+    ) -> Self {
+        let create_texture = |label, sample_count, usage| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+            })
+        };
+
+        let texture = create_texture(
+            "Layer Texture",
+            1,
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let multisample_view = (sample_count > 1).then(|| {
+            create_texture(
+                "Layer Multisample Texture",
+                sample_count,
+                wgpu::TextureUsages::RENDER_ATTACHMENT,
+            )
+            .create_view(&wgpu::TextureViewDescriptor::default())
+        });
+
+        let bind_group = Self::create_bind_group(device, bind_group_layout, uniforms, &view);
+
+        Self {
+            size: (width, height),
+            texture,
+            view,
+            multisample_view,
+            bind_group,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        uniforms: &wgpu::Buffer,
+        view: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Layer Bind Group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: uniforms,
+                        offset: 0,
+                        size: NonZeroU64::new(std::mem::size_of::<LayerUniform>() as u64),
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+            ],
+        })
+    }
+}
+
+impl Backdrop {
+    fn new(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        (width, height): (u32, u32),
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Backdrop Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Backdrop Bind Group"),
+            layout: bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(
+                    &texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                ),
+            }],
+        });
+
+        Self {
+            size: (width, height),
+            texture,
+            bind_group,
+        }
+    }
+}
+
+/// The pixels of `clip` in a target of `size`, `None` if there are none.
+pub fn scissor_rect(clip: Rect, scale: f64, (width, height): (u32, u32)) -> Option<[u32; 4]> {
+    let clip = Affine::scale(scale).transform_rect_bbox(clip).expand();
+    let x0 = clip.x0.clamp(0.0, width as f64) as u32;
+    let y0 = clip.y0.clamp(0.0, height as f64) as u32;
+    let x1 = clip.x1.clamp(0.0, width as f64) as u32;
+    let y1 = clip.y1.clamp(0.0, height as f64) as u32;
+
+    (x1 > x0 && y1 > y0).then(|| [x0, y0, x1 - x0, y1 - y0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw_calls(layers: &[u32]) -> Vec<DrawCall> {
+        layers
+            .iter()
+            .enumerate()
+            .map(|(position, layer)| DrawCall {
+                indices: position as u32..position as u32 + 1,
+                prim_offset: 0,
+                replace: false,
+                layer: *layer,
+            })
+            .collect()
+    }
+
+    fn layers(parents: &[u32]) -> Vec<Layer> {
+        std::iter::once(Layer::ROOT)
+            .chain(parents.iter().map(|parent| Layer {
+                parent: *parent,
+                ..Layer::ROOT
+            }))
+            .collect()
+    }
+
+    #[test]
+    fn frames_without_layers_draw_into_the_root() {
+        let plan = LayerPlan::new(&draw_calls(&[0, 0, 0]), &layers(&[]), false);
+
+        assert_eq!(plan.steps, [vec![Step::Draw(0..3)]]);
+        assert_eq!(plan.order, [0]);
+    }
+
+    #[test]
+    fn layers_are_composited_where_they_were_popped() {
+        // the root draws around layer 1, which contains layer 2 and draws after it
+        let plan = LayerPlan::new(&draw_calls(&[0, 1, 2, 1, 0]), &layers(&[0, 1]), false);
+
+        assert_eq!(
+            plan.steps,
+            [
+                vec![Step::Draw(0..1), Step::Composite(1), Step::Draw(4..5)],
+                vec![Step::Draw(1..2), Step::Composite(2), Step::Draw(3..4)],
+                vec![Step::Draw(2..3)],
+            ]
+        );
+        assert_eq!(plan.order, [2, 1, 0]);
+    }
+
+    #[test]
+    fn layers_without_draw_calls_are_composited_with_their_children() {
+        // layer 1 only contains layer 2, layer 3 is empty
+        let plan = LayerPlan::new(&draw_calls(&[2, 4]), &layers(&[0, 1, 0, 0]), false);
+
+        assert_eq!(
+            plan.steps,
+            [
+                vec![Step::Composite(1), Step::Composite(4)],
+                vec![Step::Composite(2)],
+                vec![Step::Draw(0..1)],
+                vec![],
+                vec![Step::Draw(1..2)],
+            ]
+        );
+        assert_eq!(plan.order, [2, 1, 4, 0]);
+    }
+
+    #[test]
+    fn layers_blended_with_the_target_get_a_root_layer() {
+        let mut layers = layers(&[0, 1]);
+        layers[1].blend_mode = BlendMode::Multiply;
+        let plan = LayerPlan::new(&draw_calls(&[0, 1, 2, 0]), &layers, false);
+
+        // the target only composites layer 3, which has what was drawn into the target
+        assert_eq!(plan.root_layer, Some(3));
+        assert_eq!(plan.layers[1].parent, 3);
+        assert_eq!(plan.layers[2].parent, 1);
+        assert_eq!(
+            plan.steps,
+            [
+                vec![Step::Composite(3)],
+                vec![Step::Draw(1..2), Step::Composite(2)],
+                vec![Step::Draw(2..3)],
+                vec![Step::Draw(0..1), Step::Composite(1), Step::Draw(3..4)],
+            ]
+        );
+        assert_eq!(plan.order, [2, 1, 3, 0]);
+    }
+
+    #[test]
+    fn targets_without_layers_can_get_a_root_layer() {
+        let plan = LayerPlan::new(&draw_calls(&[0, 0]), &layers(&[]), true);
+
+        assert_eq!(plan.root_layer, Some(1));
+        assert_eq!(
+            plan.steps,
+            [vec![Step::Composite(1)], vec![Step::Draw(0..2)]]
+        );
+        assert_eq!(plan.order, [1, 0]);
+    }
+
+    #[test]
+    fn nested_layers_blend_with_their_parent() {
+        let mut layers = layers(&[0, 1]);
+        layers[2].blend_mode = BlendMode::Difference;
+        let plan = LayerPlan::new(&draw_calls(&[1, 2]), &layers, false);
+
+        assert_eq!(plan.root_layer, None);
+        assert_eq!(plan.order, [2, 1, 0]);
+    }
+
+    #[test]
+    fn layers_share_textures_once_composited() {
+        // layers 1 and 4 are siblings, layer 2 has two children of its own
+        let mut plan = LayerPlan::new(
+            &draw_calls(&[1, 2, 3, 2, 5, 2, 4]),
+            &layers(&[0, 1, 2, 0, 2]),
+            false,
+        );
+
+        assert_eq!(plan.assign_textures(false), 3);
+        assert_eq!(
+            plan.textures,
+            [None, Some(0), Some(1), Some(2), Some(0), Some(2)]
+        );
+    }
+
+    #[test]
+    fn children_of_the_target_keep_their_textures_in_one_root_pass() {
+        let mut plan = LayerPlan::new(&draw_calls(&[1, 2, 3, 3]), &layers(&[0, 0, 0]), false);
+
+        assert_eq!(plan.assign_textures(true), 3);
+        assert_eq!(plan.assign_textures(false), 1);
+        assert_eq!(plan.textures, [None, Some(0), Some(0), Some(0)]);
+    }
+
+    #[test]
+    fn scissor_rects_cover_partial_pixels() {
+        let clip = Rect::new(0.5, -2.0, 10.25, 4.0);
+
+        assert_eq!(scissor_rect(clip, 2.0, (16, 16)), Some([1, 0, 15, 8]));
+        assert_eq!(scissor_rect(clip, 1.0, (0, 16)), None);
+    }
+}
@@ -1,10 +1,24 @@
+mod blend;
+mod buffer;
 mod buffer_layout;
 mod config;
 mod data;
 mod error;
+mod filter;
+mod frames;
+mod geometry;
+mod gpu;
 mod image;
 pub mod immediate;
+mod layer;
+#[cfg(feature = "recording")]
+pub mod recording;
 mod renderer;
+pub mod retained;
+mod screenshot;
+mod shader;
+mod staging;
+mod target;
 mod text;
 
 use std::{borrow::Cow, ops::Deref};
@@ -14,6 +28,18 @@ pub use piet::*;
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 use renderer::WgpuRenderer;
 use text::{WgpuText, WgpuTextLayout};
+pub use wgpu;
+
+pub use crate::{
+    blend::BlendMode,
+    config::{AntiAliasing, Config, SurfaceFormat},
+    error::PietWgpuError,
+    filter::Filter,
+    frames::{FrameOutput, FrameRecorder},
+    image::WgpuImage,
+    layer::RenderContextExt,
+    screenshot::encode_image,
+};
 
 pub struct PietWgpu<T>
 where
@@ -21,6 +47,8 @@ where
 {
     pub renderer: T,
     pub window: WgpuWindow,
+    error: Option<PietWgpuError>,
+    in_frame: bool,
 }
 
 impl<T> PietWgpu<T>
@@ -28,15 +56,25 @@ where
     T: WgpuRenderer,
 {
     pub fn new<W: HasRawWindowHandle + HasRawDisplayHandle>(
-        window: &W,
+        _window: &W,
         renderer: T,
         width: u32,
         height: u32,
         scale: f64,
     ) -> Self {
+        Self::from_renderer(renderer, width, height, scale)
+    }
+
+    /// Wraps a renderer that isn't tied to a window, like a headless one.
+    pub fn from_renderer(renderer: T, width: u32, height: u32, scale: f64) -> Self {
         let window = WgpuWindow::new(width, height, scale);
 
-        let mut piet_wgpu = Self { renderer, window };
+        let mut piet_wgpu = Self {
+            renderer,
+            window,
+            error: None,
+            in_frame: false,
+        };
         piet_wgpu.set_size(width, height);
         piet_wgpu
     }
@@ -52,6 +90,77 @@ where
         self.window.scale = scale_factor;
         self.renderer.set_scale(scale_factor);
     }
+
+    /// Starts a new frame, nothing drawn in earlier frames is shown anymore.
+    ///
+    /// A frame lasts until `finish`, which renders and presents it. Drawing without calling
+    /// `begin_frame` first starts a frame implicitly, so every frame has to be drawn in full.
+    /// Images stay uploaded between frames as long as they (or their clones) are drawn again.
+    pub fn begin_frame(&mut self) {
+        self.renderer.begin_frame();
+        self.in_frame = true;
+    }
+
+    /// Ends the frame like `finish`, but uploads it with the caller's `encoder` instead of
+    /// rendering it, so it can be drawn into a render pass of the caller with `paint`.
+    ///
+    /// `device` and `queue` have to be the ones the renderer was created with.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+    ) -> Result<(), Error> {
+        self.ensure_frame();
+        self.in_frame = false;
+
+        let result = self.renderer.prepare(device, queue, encoder);
+        self.record_error(result);
+        self.status()
+    }
+
+    /// Draws the frame uploaded by `prepare` into `render_pass`.
+    pub fn paint<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.renderer.paint(render_pass);
+    }
+
+    /// The last finished frame as an image of the whole target, with separate alpha and sRGB
+    /// encoded colors.
+    ///
+    /// The frame is rendered again into a texture of its own, so this works for windows and
+    /// headless targets alike. After `prepare`, the caller's encoder has to be submitted first.
+    pub fn screenshot(&mut self) -> Result<::image::RgbaImage, Error> {
+        if self.in_frame {
+            return Err(PietWgpuError::FrameInProgress.into());
+        }
+
+        Ok(self.renderer.screenshot()?)
+    }
+
+    /// Saves a screenshot to `path`, encoded in the format of its extension like png or jpeg.
+    pub fn save_screenshot(&mut self, path: impl AsRef<std::path::Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let format = ::image::ImageFormat::from_path(path).map_err(PietWgpuError::from)?;
+        let encoded = encode_image(&self.screenshot()?, format)?;
+
+        std::fs::write(path, encoded).map_err(|error| PietWgpuError::from(error).into())
+    }
+
+    fn ensure_frame(&mut self) {
+        if !self.in_frame {
+            self.begin_frame();
+        }
+    }
+
+    /// Keeps the first error of a draw call until it is reported by `status` or `finish`.
+    fn record_error(&mut self, result: error::Result<()>) {
+        if let Err(error) = result {
+            match self.error {
+                Some(_) => log::warn!("Dropping draw error after earlier error: {error}"),
+                None => self.error = Some(error),
+            }
+        }
+    }
 }
 
 pub struct WgpuWindow {
@@ -79,33 +188,32 @@ pub enum WgpuBrush {
 impl<T: WgpuRenderer> IntoBrush<PietWgpu<T>> for WgpuBrush {
     fn make_brush<'a>(
         &'a self,
-        piet: &mut PietWgpu<T>,
-        bbox: impl FnOnce() -> kurbo::Rect,
+        _piet: &mut PietWgpu<T>,
+        _bbox: impl FnOnce() -> kurbo::Rect,
     ) -> std::borrow::Cow<'a, <PietWgpu<T> as RenderContext>::Brush> {
-        Cow::Owned(WgpuBrush::Solid(Color::grey(0.5))) // TODO
-    }
-}
-
-#[derive(Clone)]
-pub struct WgpuImage {
-    dynamic: DynamicImage,
-}
-
-impl WgpuImage {
-    pub fn from_bytes(bytes: &[u8]) -> Self {
-        let image = image::load_from_memory(bytes).unwrap();
-        Self { dynamic: image }
+        Cow::Borrowed(self)
     }
 }
 
-impl piet::Image for WgpuImage {
-    fn size(&self) -> Size {
-        let (width, height) = self.dynamic.dimensions();
+impl WgpuBrush {
+    /// Color of the primitives drawn with the brush, sRGB like piet's colors. Gradients aren't
+    /// implemented yet and fill with the color of their first stop.
+    pub(crate) fn color(&self) -> [f32; 4] {
+        let color = match self {
+            WgpuBrush::Solid(color) => color,
+            WgpuBrush::Gradient(gradient) => {
+                let stops = match gradient {
+                    FixedGradient::Linear(gradient) => &gradient.stops,
+                    FixedGradient::Radial(gradient) => &gradient.stops,
+                };
+                stops
+                    .first()
+                    .map_or(&Color::TRANSPARENT, |stop| &stop.color)
+            }
+        };
 
-        Size {
-            width: width.into(),
-            height: height.into(),
-        }
+        let (r, g, b, a) = color.as_rgba();
+        [r as f32, g as f32, b as f32, a as f32]
     }
 }
 
@@ -119,7 +227,10 @@ impl<T: WgpuRenderer> piet::RenderContext for PietWgpu<T> {
     type Image = WgpuImage;
 
     fn status(&mut self) -> Result<(), Error> {
-        todo!()
+        match self.error.take() {
+            Some(error) => Err(error.into()),
+            None => Ok(()),
+        }
     }
 
     fn solid_brush(&mut self, color: Color) -> Self::Brush {
@@ -133,14 +244,17 @@ impl<T: WgpuRenderer> piet::RenderContext for PietWgpu<T> {
     fn clear(&mut self, region: impl Into<Option<kurbo::Rect>>, color: Color) {
         let (r, g, b, a) = color.as_rgba();
         let region: Option<kurbo::Rect> = region.into();
-        match region {
-            Some(rect) => todo!(),
+        self.ensure_frame();
+        // clearing ignores the current transform and clip, so this goes straight to the renderer
+        let result = match region {
+            Some(rect) => self.renderer.clear_rect(rect, wgpu::Color { r, g, b, a }),
             None => self.renderer.clear_all(wgpu::Color { r, g, b, a }),
-        }
+        };
+        self.record_error(result);
     }
 
     fn stroke(&mut self, shape: impl kurbo::Shape, brush: &impl IntoBrush<Self>, width: f64) {
-        todo!()
+        self.stroke_styled(shape, brush, width, &StrokeStyle::default());
     }
 
     fn stroke_styled(
@@ -150,23 +264,36 @@ impl<T: WgpuRenderer> piet::RenderContext for PietWgpu<T> {
         width: f64,
         style: &StrokeStyle,
     ) {
-        todo!()
+        let brush: std::borrow::Cow<'_, WgpuBrush> =
+            brush.make_brush(self, || Rect::new(0.0, 0.0, 0.0, 0.0)); // TODO implement bounding box
+        self.ensure_frame();
+
+        let mut path = geometry::shape_path(shape);
+        if !style.dash_pattern.is_empty() {
+            path = geometry::dash(&path, &style.dash_pattern, style.dash_offset);
+        }
+
+        let options = geometry::stroke_options(width, style);
+        let result = self.renderer.stroke(path, &options, brush.deref());
+        self.record_error(result);
     }
 
     fn fill(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
         let brush: std::borrow::Cow<'_, WgpuBrush> =
             brush.make_brush(self, || Rect::new(0.0, 0.0, 0.0, 0.0)); // TODO implement bounding box
+        self.ensure_frame();
 
-        if let Some(rect) = shape.as_rect() {
-            self.renderer.fill_rect(rect, brush.deref());
-        }
+        let result = self
+            .renderer
+            .fill(geometry::shape_path(shape), brush.deref());
+        self.record_error(result);
     }
 
-    fn fill_even_odd(&mut self, shape: impl kurbo::Shape, brush: &impl IntoBrush<Self>) {
+    fn fill_even_odd(&mut self, _shape: impl kurbo::Shape, _brush: &impl IntoBrush<Self>) {
         todo!()
     }
 
-    fn clip(&mut self, shape: impl kurbo::Shape) {
+    fn clip(&mut self, _shape: impl kurbo::Shape) {
         todo!()
     }
 
@@ -174,7 +301,7 @@ impl<T: WgpuRenderer> piet::RenderContext for PietWgpu<T> {
         todo!()
     }
 
-    fn draw_text(&mut self, layout: &Self::TextLayout, pos: impl Into<kurbo::Point>) {
+    fn draw_text(&mut self, _layout: &Self::TextLayout, _pos: impl Into<kurbo::Point>) {
         todo!()
     }
 
@@ -187,21 +314,24 @@ impl<T: WgpuRenderer> piet::RenderContext for PietWgpu<T> {
     }
 
     fn finish(&mut self) -> Result<(), Error> {
-        self.renderer
-            .finish()
-            .map_err(|e| piet::Error::BackendError(Box::new(e)))
+        self.ensure_frame();
+        self.in_frame = false;
+
+        let result = self.renderer.end_frame();
+        self.record_error(result);
+        self.status()
     }
 
-    fn transform(&mut self, transform: kurbo::Affine) {
+    fn transform(&mut self, _transform: kurbo::Affine) {
         todo!()
     }
 
     fn make_image(
         &mut self,
-        width: usize,
-        height: usize,
-        buf: &[u8],
-        format: ImageFormat,
+        _width: usize,
+        _height: usize,
+        _buf: &[u8],
+        _format: ImageFormat,
     ) -> Result<Self::Image, Error> {
         todo!()
     }
@@ -210,29 +340,36 @@ impl<T: WgpuRenderer> piet::RenderContext for PietWgpu<T> {
         &mut self,
         image: &Self::Image,
         dst_rect: impl Into<kurbo::Rect>,
-        interp: InterpolationMode,
+        _interp: InterpolationMode,
     ) {
-        self.renderer.draw_image(dst_rect.into(), image);
+        self.ensure_frame();
+        let result = self.renderer.draw_image(dst_rect.into(), image);
+        self.record_error(result);
     }
 
     fn draw_image_area(
         &mut self,
-        image: &Self::Image,
-        src_rect: impl Into<kurbo::Rect>,
-        dst_rect: impl Into<kurbo::Rect>,
-        interp: InterpolationMode,
+        _image: &Self::Image,
+        _src_rect: impl Into<kurbo::Rect>,
+        _dst_rect: impl Into<kurbo::Rect>,
+        _interp: InterpolationMode,
     ) {
         todo!()
     }
 
     fn capture_image_area(
         &mut self,
-        src_rect: impl Into<kurbo::Rect>,
+        _src_rect: impl Into<kurbo::Rect>,
     ) -> Result<Self::Image, Error> {
         todo!()
     }
 
-    fn blurred_rect(&mut self, rect: kurbo::Rect, blur_radius: f64, brush: &impl IntoBrush<Self>) {
+    fn blurred_rect(
+        &mut self,
+        _rect: kurbo::Rect,
+        _blur_radius: f64,
+        _brush: &impl IntoBrush<Self>,
+    ) {
         todo!()
     }
 
@@ -0,0 +1,569 @@
+use std::sync::Arc;
+
+use piet::{
+    kurbo::{Affine, BezPath, Circle, Line, Point, Rect, RoundedRect, Shape as _, Vec2},
+    util::resolve_range,
+    Color, Error, FixedGradient, FixedLinearGradient, FixedRadialGradient, FontFamily, FontWeight,
+    InterpolationMode, RenderContext, Text, TextLayoutBuilder,
+};
+use serde::{Deserialize, Serialize};
+
+/// Everything drawn into a [`Recorder`](super::Recorder), in the order it was drawn.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DisplayList {
+    /// Images used by the commands, referred to by their index.
+    pub images: Vec<Arc<ImageData>>,
+    pub commands: Vec<Command>,
+}
+
+/// A piet call, with everything needed to make it again.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Command {
+    Clear {
+        region: Option<Rect>,
+        #[serde(with = "color")]
+        color: Color,
+    },
+    Fill {
+        shape: Shape,
+        brush: Brush,
+    },
+    FillEvenOdd {
+        shape: Shape,
+        brush: Brush,
+    },
+    Stroke {
+        shape: Shape,
+        brush: Brush,
+        width: f64,
+        style: StrokeStyle,
+    },
+    Clip {
+        shape: Shape,
+    },
+    DrawText {
+        layout: TextLayoutData,
+        position: Point,
+    },
+    Save,
+    Restore,
+    Transform(Affine),
+    DrawImage {
+        image: usize,
+        dst_rect: Rect,
+        interpolation: Interpolation,
+    },
+    DrawImageArea {
+        image: usize,
+        src_rect: Rect,
+        dst_rect: Rect,
+        interpolation: Interpolation,
+    },
+    /// Defines image `image` as the captured area, its data in the list is a transparent
+    /// placeholder.
+    CaptureImageArea {
+        image: usize,
+        src_rect: Rect,
+    },
+    BlurredRect {
+        rect: Rect,
+        blur_radius: f64,
+        brush: Brush,
+    },
+}
+
+/// A shape, kept as what it was drawn as since some render contexts only draw certain shapes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Shape {
+    Rect(Rect),
+    RoundedRect(RoundedRect),
+    Circle(Circle),
+    Line(Line),
+    Path(BezPath),
+}
+
+impl Shape {
+    pub fn new(shape: impl piet::kurbo::Shape) -> Self {
+        if let Some(rect) = shape.as_rect() {
+            Shape::Rect(rect)
+        } else if let Some(rect) = shape.as_rounded_rect() {
+            Shape::RoundedRect(rect)
+        } else if let Some(circle) = shape.as_circle() {
+            Shape::Circle(circle)
+        } else if let Some(line) = shape.as_line() {
+            Shape::Line(line)
+        } else {
+            Shape::Path(shape.into_path(0.1))
+        }
+    }
+
+    pub fn bounding_box(&self) -> Rect {
+        match self {
+            Shape::Rect(rect) => rect.bounding_box(),
+            Shape::RoundedRect(rect) => rect.bounding_box(),
+            Shape::Circle(circle) => circle.bounding_box(),
+            Shape::Line(line) => line.bounding_box(),
+            Shape::Path(path) => path.bounding_box(),
+        }
+    }
+}
+
+/// Calls `$draw` with the kurbo shape inside of `$shape`.
+macro_rules! with_shape {
+    ($shape:expr, |$name:ident| $draw:expr) => {
+        match $shape {
+            Shape::Rect($name) => $draw,
+            Shape::RoundedRect($name) => $draw,
+            Shape::Circle($name) => $draw,
+            Shape::Line($name) => $draw,
+            Shape::Path($name) => $draw,
+        }
+    };
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Brush {
+    Solid(#[serde(with = "color")] Color),
+    LinearGradient {
+        start: Point,
+        end: Point,
+        stops: Vec<GradientStop>,
+    },
+    RadialGradient {
+        center: Point,
+        origin_offset: Vec2,
+        radius: f64,
+        stops: Vec<GradientStop>,
+    },
+}
+
+impl Brush {
+    fn make<R: RenderContext>(&self, ctx: &mut R) -> Result<R::Brush, Error> {
+        match self {
+            Brush::Solid(color) => Ok(ctx.solid_brush(color.clone())),
+            Brush::LinearGradient { start, end, stops } => {
+                ctx.gradient(FixedGradient::Linear(FixedLinearGradient {
+                    start: *start,
+                    end: *end,
+                    stops: stops.iter().map(Into::into).collect(),
+                }))
+            }
+            Brush::RadialGradient {
+                center,
+                origin_offset,
+                radius,
+                stops,
+            } => ctx.gradient(FixedGradient::Radial(FixedRadialGradient {
+                center: *center,
+                origin_offset: *origin_offset,
+                radius: *radius,
+                stops: stops.iter().map(Into::into).collect(),
+            })),
+        }
+    }
+}
+
+impl From<FixedGradient> for Brush {
+    fn from(gradient: FixedGradient) -> Self {
+        match gradient {
+            FixedGradient::Linear(gradient) => Brush::LinearGradient {
+                start: gradient.start,
+                end: gradient.end,
+                stops: gradient.stops.iter().map(Into::into).collect(),
+            },
+            FixedGradient::Radial(gradient) => Brush::RadialGradient {
+                center: gradient.center,
+                origin_offset: gradient.origin_offset,
+                radius: gradient.radius,
+                stops: gradient.stops.iter().map(Into::into).collect(),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GradientStop {
+    pub pos: f32,
+    #[serde(with = "color")]
+    pub color: Color,
+}
+
+impl From<&piet::GradientStop> for GradientStop {
+    fn from(stop: &piet::GradientStop) -> Self {
+        Self {
+            pos: stop.pos,
+            color: stop.color.clone(),
+        }
+    }
+}
+
+impl From<&GradientStop> for piet::GradientStop {
+    fn from(stop: &GradientStop) -> Self {
+        Self {
+            pos: stop.pos,
+            color: stop.color.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StrokeStyle {
+    #[serde(with = "LineJoinDef")]
+    pub line_join: piet::LineJoin,
+    #[serde(with = "LineCapDef")]
+    pub line_cap: piet::LineCap,
+    pub dash_pattern: Vec<f64>,
+    pub dash_offset: f64,
+}
+
+impl From<&piet::StrokeStyle> for StrokeStyle {
+    fn from(style: &piet::StrokeStyle) -> Self {
+        Self {
+            line_join: style.line_join,
+            line_cap: style.line_cap,
+            dash_pattern: style.dash_pattern.to_vec(),
+            dash_offset: style.dash_offset,
+        }
+    }
+}
+
+impl From<&StrokeStyle> for piet::StrokeStyle {
+    fn from(style: &StrokeStyle) -> Self {
+        let mut piet_style = piet::StrokeStyle::new()
+            .line_join(style.line_join)
+            .line_cap(style.line_cap)
+            .dash_offset(style.dash_offset);
+        piet_style.set_dash_pattern(style.dash_pattern.as_slice());
+        piet_style
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "piet::LineJoin")]
+enum LineJoinDef {
+    Miter { limit: f64 },
+    Round,
+    Bevel,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "piet::LineCap")]
+enum LineCapDef {
+    Butt,
+    Round,
+    Square,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interpolation {
+    NearestNeighbor,
+    Bilinear,
+}
+
+impl From<InterpolationMode> for Interpolation {
+    fn from(mode: InterpolationMode) -> Self {
+        match mode {
+            InterpolationMode::NearestNeighbor => Interpolation::NearestNeighbor,
+            InterpolationMode::Bilinear => Interpolation::Bilinear,
+        }
+    }
+}
+
+impl From<Interpolation> for InterpolationMode {
+    fn from(mode: Interpolation) -> Self {
+        match mode {
+            Interpolation::NearestNeighbor => InterpolationMode::NearestNeighbor,
+            Interpolation::Bilinear => InterpolationMode::Bilinear,
+        }
+    }
+}
+
+/// Pixels of an image as passed to `make_image`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageData {
+    pub width: usize,
+    pub height: usize,
+    pub format: ImageFormat,
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ImageFormat {
+    Grayscale,
+    Rgb,
+    RgbaSeparate,
+    RgbaPremul,
+}
+
+impl ImageFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        piet::ImageFormat::from(self).bytes_per_pixel()
+    }
+}
+
+impl TryFrom<piet::ImageFormat> for ImageFormat {
+    type Error = Error;
+
+    fn try_from(format: piet::ImageFormat) -> Result<Self, Error> {
+        match format {
+            piet::ImageFormat::Grayscale => Ok(ImageFormat::Grayscale),
+            piet::ImageFormat::Rgb => Ok(ImageFormat::Rgb),
+            piet::ImageFormat::RgbaSeparate => Ok(ImageFormat::RgbaSeparate),
+            piet::ImageFormat::RgbaPremul => Ok(ImageFormat::RgbaPremul),
+            _ => Err(Error::NotSupported),
+        }
+    }
+}
+
+impl From<ImageFormat> for piet::ImageFormat {
+    fn from(format: ImageFormat) -> Self {
+        match format {
+            ImageFormat::Grayscale => piet::ImageFormat::Grayscale,
+            ImageFormat::Rgb => piet::ImageFormat::Rgb,
+            ImageFormat::RgbaSeparate => piet::ImageFormat::RgbaSeparate,
+            ImageFormat::RgbaPremul => piet::ImageFormat::RgbaPremul,
+        }
+    }
+}
+
+/// Text and attributes of a text layout, laid out again when replayed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TextLayoutData {
+    pub text: String,
+    /// Width to wrap the text at, `None` for no wrapping.
+    pub max_width: Option<f64>,
+    pub alignment: Option<TextAlignment>,
+    pub default_attributes: Vec<TextAttribute>,
+    /// Attributes of byte ranges of the text.
+    pub range_attributes: Vec<(std::ops::Range<usize>, TextAttribute)>,
+}
+
+impl TextLayoutData {
+    fn make<R: RenderContext>(&self, ctx: &mut R) -> Result<R::TextLayout, Error> {
+        let mut builder = ctx.text().new_text_layout(self.text.clone());
+
+        if let Some(width) = self.max_width {
+            builder = builder.max_width(width);
+        }
+        if let Some(alignment) = self.alignment {
+            builder = builder.alignment(alignment.into());
+        }
+        for attribute in &self.default_attributes {
+            builder = builder.default_attribute(attribute.clone());
+        }
+        for (range, attribute) in &self.range_attributes {
+            builder = builder.range_attribute(range.clone(), attribute.clone());
+        }
+
+        builder.build()
+    }
+
+    pub(super) fn push_range_attribute(
+        &mut self,
+        range: impl std::ops::RangeBounds<usize>,
+        attribute: impl Into<piet::TextAttribute>,
+    ) {
+        let range = resolve_range(range, self.text.len());
+        self.range_attributes.push((range, attribute.into().into()));
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextAlignment {
+    Start,
+    End,
+    Center,
+    Justified,
+}
+
+impl From<piet::TextAlignment> for TextAlignment {
+    fn from(alignment: piet::TextAlignment) -> Self {
+        match alignment {
+            piet::TextAlignment::Start => TextAlignment::Start,
+            piet::TextAlignment::End => TextAlignment::End,
+            piet::TextAlignment::Center => TextAlignment::Center,
+            piet::TextAlignment::Justified => TextAlignment::Justified,
+        }
+    }
+}
+
+impl From<TextAlignment> for piet::TextAlignment {
+    fn from(alignment: TextAlignment) -> Self {
+        match alignment {
+            TextAlignment::Start => piet::TextAlignment::Start,
+            TextAlignment::End => piet::TextAlignment::End,
+            TextAlignment::Center => piet::TextAlignment::Center,
+            TextAlignment::Justified => piet::TextAlignment::Justified,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TextAttribute {
+    /// A font family by name, generic families have the names of their css keywords.
+    FontFamily(String),
+    FontSize(f64),
+    Weight(u16),
+    TextColor(#[serde(with = "color")] Color),
+    Italic(bool),
+    Underline(bool),
+    Strikethrough(bool),
+}
+
+impl From<piet::TextAttribute> for TextAttribute {
+    fn from(attribute: piet::TextAttribute) -> Self {
+        match attribute {
+            piet::TextAttribute::FontFamily(family) => {
+                TextAttribute::FontFamily(family.name().to_string())
+            }
+            piet::TextAttribute::FontSize(size) => TextAttribute::FontSize(size),
+            piet::TextAttribute::Weight(weight) => TextAttribute::Weight(weight.to_raw()),
+            piet::TextAttribute::TextColor(color) => TextAttribute::TextColor(color),
+            piet::TextAttribute::Style(style) => {
+                TextAttribute::Italic(style == piet::FontStyle::Italic)
+            }
+            piet::TextAttribute::Underline(underline) => TextAttribute::Underline(underline),
+            piet::TextAttribute::Strikethrough(strikethrough) => {
+                TextAttribute::Strikethrough(strikethrough)
+            }
+        }
+    }
+}
+
+impl From<TextAttribute> for piet::TextAttribute {
+    fn from(attribute: TextAttribute) -> Self {
+        match attribute {
+            TextAttribute::FontFamily(name) => piet::TextAttribute::FontFamily(font_family(&name)),
+            TextAttribute::FontSize(size) => piet::TextAttribute::FontSize(size),
+            TextAttribute::Weight(weight) => piet::TextAttribute::Weight(FontWeight::new(weight)),
+            TextAttribute::TextColor(color) => piet::TextAttribute::TextColor(color),
+            TextAttribute::Italic(italic) => piet::TextAttribute::Style(match italic {
+                true => piet::FontStyle::Italic,
+                false => piet::FontStyle::Regular,
+            }),
+            TextAttribute::Underline(underline) => piet::TextAttribute::Underline(underline),
+            TextAttribute::Strikethrough(strikethrough) => {
+                piet::TextAttribute::Strikethrough(strikethrough)
+            }
+        }
+    }
+}
+
+/// The font family named `name`, generic ones by their css keyword.
+pub(super) fn font_family(name: &str) -> FontFamily {
+    [
+        FontFamily::SERIF,
+        FontFamily::SANS_SERIF,
+        FontFamily::MONOSPACE,
+        FontFamily::SYSTEM_UI,
+    ]
+    .into_iter()
+    .find(|family| family.name() == name)
+    .unwrap_or_else(|| FontFamily::new_unchecked(name))
+}
+
+/// Colors as their rgba8 value, like `0xff0000ff` for red.
+mod color {
+    use piet::Color;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        color.as_rgba_u32().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        u32::deserialize(deserializer).map(Color::from_rgba32_u32)
+    }
+}
+
+impl DisplayList {
+    /// Makes the recorded calls on `ctx`, stopping at the first error.
+    ///
+    /// Text is laid out again by `ctx`, captured images are captured from what `ctx` drew.
+    /// `finish` isn't called, so more can be drawn on top.
+    pub fn replay<R: RenderContext>(&self, ctx: &mut R) -> Result<(), Error> {
+        let mut images: Vec<Option<R::Image>> = vec![None; self.images.len()];
+
+        for command in &self.commands {
+            match command {
+                Command::Clear { region, color } => ctx.clear(*region, color.clone()),
+                Command::Fill { shape, brush } => {
+                    let brush = brush.make(ctx)?;
+                    with_shape!(shape, |shape| ctx.fill(shape, &brush));
+                }
+                Command::FillEvenOdd { shape, brush } => {
+                    let brush = brush.make(ctx)?;
+                    with_shape!(shape, |shape| ctx.fill_even_odd(shape, &brush));
+                }
+                Command::Stroke {
+                    shape,
+                    brush,
+                    width,
+                    style,
+                } => {
+                    let brush = brush.make(ctx)?;
+                    let style = style.into();
+                    with_shape!(shape, |shape| ctx
+                        .stroke_styled(shape, &brush, *width, &style));
+                }
+                Command::Clip { shape } => with_shape!(shape, |shape| ctx.clip(shape)),
+                Command::DrawText { layout, position } => {
+                    let layout = layout.make(ctx)?;
+                    ctx.draw_text(&layout, *position);
+                }
+                Command::Save => ctx.save()?,
+                Command::Restore => ctx.restore()?,
+                Command::Transform(transform) => ctx.transform(*transform),
+                Command::DrawImage {
+                    image,
+                    dst_rect,
+                    interpolation,
+                } => {
+                    let image = self.image(ctx, &mut images, *image)?;
+                    ctx.draw_image(image, *dst_rect, (*interpolation).into());
+                }
+                Command::DrawImageArea {
+                    image,
+                    src_rect,
+                    dst_rect,
+                    interpolation,
+                } => {
+                    let image = self.image(ctx, &mut images, *image)?;
+                    ctx.draw_image_area(image, *src_rect, *dst_rect, (*interpolation).into());
+                }
+                Command::CaptureImageArea { image, src_rect } => {
+                    let captured = ctx.capture_image_area(*src_rect)?;
+                    *images.get_mut(*image).ok_or(Error::InvalidInput)? = Some(captured);
+                }
+                Command::BlurredRect {
+                    rect,
+                    blur_radius,
+                    brush,
+                } => {
+                    let brush = brush.make(ctx)?;
+                    ctx.blurred_rect(*rect, *blur_radius, &brush);
+                }
+            }
+        }
+
+        ctx.status()
+    }
+
+    /// Image `index` of `ctx`, made the first time it is drawn.
+    fn image<'a, R: RenderContext>(
+        &self,
+        ctx: &mut R,
+        images: &'a mut [Option<R::Image>],
+        index: usize,
+    ) -> Result<&'a R::Image, Error> {
+        let data = self.images.get(index).ok_or(Error::InvalidInput)?;
+        let image = &mut images[index];
+
+        if image.is_none() {
+            *image =
+                Some(ctx.make_image(data.width, data.height, &data.pixels, data.format.into())?);
+        }
+
+        Ok(image.as_ref().unwrap())
+    }
+}
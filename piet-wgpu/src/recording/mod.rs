@@ -0,0 +1,277 @@
+//! Recording piet calls into a serializable [`DisplayList`], to save and replay them later.
+//!
+//! The [`Recorder`] is a render context that draws nothing and needs no gpu, which makes it
+//! useful for testing drawing code too.
+
+mod list;
+mod svg;
+mod text;
+
+use std::{borrow::Cow, sync::Arc};
+
+use piet::{
+    kurbo::{Affine, Point, Rect, Size},
+    Color, Error, FixedGradient, InterpolationMode, IntoBrush, RenderContext,
+};
+
+pub use self::{
+    list::{
+        Brush, Command, DisplayList, GradientStop, ImageData, ImageFormat, Interpolation, Shape,
+        StrokeStyle, TextAlignment, TextAttribute, TextLayoutData,
+    },
+    text::{RecordedText, RecordedTextLayout, RecordedTextLayoutBuilder},
+};
+
+/// A render context appending every call to a display list instead of drawing.
+#[derive(Default)]
+pub struct Recorder {
+    list: DisplayList,
+    text: RecordedText,
+    transform: Affine,
+    saved_transforms: Vec<Affine>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The calls recorded since the recorder was created or last taken from.
+    pub fn display_list(&self) -> &DisplayList {
+        &self.list
+    }
+
+    /// Takes the recorded calls, the following ones go to a new display list.
+    ///
+    /// Transform and saved states are kept, images are added to the new list again when drawn.
+    pub fn take(&mut self) -> DisplayList {
+        std::mem::take(&mut self.list)
+    }
+
+    fn push(&mut self, command: Command) {
+        self.list.commands.push(command);
+    }
+
+    /// Index of `image` in the list, it is added the first time it is drawn.
+    fn image_index(&mut self, image: &RecordedImage) -> usize {
+        match self
+            .list
+            .images
+            .iter()
+            .position(|data| Arc::ptr_eq(data, &image.0))
+        {
+            Some(index) => index,
+            None => {
+                self.list.images.push(image.0.clone());
+                self.list.images.len() - 1
+            }
+        }
+    }
+
+    fn brush(&mut self, brush: &impl IntoBrush<Self>, shape: &Shape) -> Brush {
+        brush.make_brush(self, || shape.bounding_box()).into_owned()
+    }
+}
+
+impl IntoBrush<Recorder> for Brush {
+    fn make_brush<'a>(
+        &'a self,
+        _piet: &mut Recorder,
+        _bbox: impl FnOnce() -> Rect,
+    ) -> Cow<'a, Brush> {
+        Cow::Borrowed(self)
+    }
+}
+
+/// An image of the [`Recorder`], clones share their data.
+#[derive(Clone)]
+pub struct RecordedImage(Arc<ImageData>);
+
+impl piet::Image for RecordedImage {
+    fn size(&self) -> Size {
+        Size::new(self.0.width as f64, self.0.height as f64)
+    }
+}
+
+impl RenderContext for Recorder {
+    type Brush = Brush;
+
+    type Text = RecordedText;
+
+    type TextLayout = RecordedTextLayout;
+
+    type Image = RecordedImage;
+
+    fn status(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn solid_brush(&mut self, color: Color) -> Self::Brush {
+        Brush::Solid(color)
+    }
+
+    fn gradient(&mut self, gradient: impl Into<FixedGradient>) -> Result<Self::Brush, Error> {
+        Ok(gradient.into().into())
+    }
+
+    fn clear(&mut self, region: impl Into<Option<Rect>>, color: Color) {
+        self.push(Command::Clear {
+            region: region.into(),
+            color,
+        });
+    }
+
+    fn stroke(&mut self, shape: impl piet::kurbo::Shape, brush: &impl IntoBrush<Self>, width: f64) {
+        self.stroke_styled(shape, brush, width, &piet::StrokeStyle::new());
+    }
+
+    fn stroke_styled(
+        &mut self,
+        shape: impl piet::kurbo::Shape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &piet::StrokeStyle,
+    ) {
+        let shape = Shape::new(shape);
+        let brush = self.brush(brush, &shape);
+        self.push(Command::Stroke {
+            shape,
+            brush,
+            width,
+            style: style.into(),
+        });
+    }
+
+    fn fill(&mut self, shape: impl piet::kurbo::Shape, brush: &impl IntoBrush<Self>) {
+        let shape = Shape::new(shape);
+        let brush = self.brush(brush, &shape);
+        self.push(Command::Fill { shape, brush });
+    }
+
+    fn fill_even_odd(&mut self, shape: impl piet::kurbo::Shape, brush: &impl IntoBrush<Self>) {
+        let shape = Shape::new(shape);
+        let brush = self.brush(brush, &shape);
+        self.push(Command::FillEvenOdd { shape, brush });
+    }
+
+    fn clip(&mut self, shape: impl piet::kurbo::Shape) {
+        self.push(Command::Clip {
+            shape: Shape::new(shape),
+        });
+    }
+
+    fn text(&mut self) -> &mut Self::Text {
+        &mut self.text
+    }
+
+    fn draw_text(&mut self, layout: &Self::TextLayout, pos: impl Into<Point>) {
+        self.push(Command::DrawText {
+            layout: layout.data.clone(),
+            position: pos.into(),
+        });
+    }
+
+    fn save(&mut self) -> Result<(), Error> {
+        self.saved_transforms.push(self.transform);
+        self.push(Command::Save);
+        Ok(())
+    }
+
+    fn restore(&mut self) -> Result<(), Error> {
+        self.transform = self.saved_transforms.pop().ok_or(Error::StackUnbalance)?;
+        self.push(Command::Restore);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn transform(&mut self, transform: Affine) {
+        self.transform *= transform;
+        self.push(Command::Transform(transform));
+    }
+
+    fn make_image(
+        &mut self,
+        width: usize,
+        height: usize,
+        buf: &[u8],
+        format: piet::ImageFormat,
+    ) -> Result<Self::Image, Error> {
+        let size = width * height * format.bytes_per_pixel();
+        if buf.len() < size {
+            return Err(Error::InvalidInput);
+        }
+
+        Ok(RecordedImage(Arc::new(ImageData {
+            width,
+            height,
+            format: format.try_into()?,
+            pixels: buf[..size].to_vec(),
+        })))
+    }
+
+    fn draw_image(
+        &mut self,
+        image: &Self::Image,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        let image = self.image_index(image);
+        self.push(Command::DrawImage {
+            image,
+            dst_rect: dst_rect.into(),
+            interpolation: interp.into(),
+        });
+    }
+
+    fn draw_image_area(
+        &mut self,
+        image: &Self::Image,
+        src_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        let image = self.image_index(image);
+        self.push(Command::DrawImageArea {
+            image,
+            src_rect: src_rect.into(),
+            dst_rect: dst_rect.into(),
+            interpolation: interp.into(),
+        });
+    }
+
+    /// Records the capture, the image itself is transparent since nothing is drawn.
+    fn capture_image_area(&mut self, src_rect: impl Into<Rect>) -> Result<Self::Image, Error> {
+        let src_rect = src_rect.into();
+        let (width, height) = (src_rect.width() as usize, src_rect.height() as usize);
+
+        let image = self.make_image(
+            width,
+            height,
+            &vec![0; width * height * 4],
+            piet::ImageFormat::RgbaPremul,
+        )?;
+        let index = self.image_index(&image);
+        self.push(Command::CaptureImageArea {
+            image: index,
+            src_rect,
+        });
+
+        Ok(image)
+    }
+
+    fn blurred_rect(&mut self, rect: Rect, blur_radius: f64, brush: &impl IntoBrush<Self>) {
+        let brush = self.brush(brush, &Shape::Rect(rect));
+        self.push(Command::BlurredRect {
+            rect,
+            blur_radius,
+            brush,
+        });
+    }
+
+    fn current_transform(&self) -> Affine {
+        self.transform
+    }
+}
@@ -0,0 +1,542 @@
+use std::{fmt::Write as _, io::Cursor, ops::Range};
+
+use base64::Engine;
+use image::{
+    error::{ImageError, ParameterError, ParameterErrorKind},
+    RgbaImage,
+};
+use piet::{
+    kurbo::{Affine, Shape as _, Size},
+    Color, LineCap, LineJoin, TextLayout,
+};
+
+use super::{
+    list::{Brush, Command, DisplayList, ImageData, ImageFormat, Shape, StrokeStyle},
+    text::{RecordedTextLayout, DEFAULT_FONT_SIZE},
+    TextAlignment, TextAttribute,
+};
+use crate::error::Result;
+
+impl DisplayList {
+    /// The recorded drawing as svg document of `size`.
+    ///
+    /// Images are embedded as png, text is drawn with the fonts of the viewer. Region clears
+    /// with translucent colors are blended with what's below instead of replacing it, and
+    /// captured images are transparent.
+    pub fn to_svg(&self, size: Size) -> Result<String> {
+        let mut svg = Svg::default();
+
+        for (index, image) in self.images.iter().enumerate() {
+            svg.define_image(index, image)?;
+        }
+        for command in &self.commands {
+            svg.command(command, size);
+        }
+
+        Ok(svg.finish(size))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct State {
+    transform: Affine,
+    /// Id of the clip path of everything drawn, the intersection of all clips.
+    clip: Option<usize>,
+}
+
+#[derive(Default)]
+struct Svg {
+    defs: String,
+    body: String,
+    state: State,
+    saved: Vec<State>,
+    next_id: usize,
+    image_sizes: Vec<Size>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            transform: Affine::IDENTITY,
+            clip: None,
+        }
+    }
+}
+
+impl Svg {
+    fn id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn command(&mut self, command: &Command, size: Size) {
+        match command {
+            Command::Clear { region, color } => {
+                // everything is replaced by a full clear
+                if region.is_none() {
+                    self.body.clear();
+                }
+
+                let rect = region.unwrap_or_else(|| size.to_rect());
+                let color = paint(color);
+                self.element(
+                    None,
+                    None,
+                    &format!(
+                        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" fill-opacity="{}"/>"#,
+                        rect.x0,
+                        rect.y0,
+                        rect.width(),
+                        rect.height(),
+                        color.0,
+                        color.1
+                    ),
+                );
+            }
+            Command::Fill { shape, brush } => self.fill(shape, brush, "nonzero"),
+            Command::FillEvenOdd { shape, brush } => self.fill(shape, brush, "evenodd"),
+            Command::Stroke {
+                shape,
+                brush,
+                width,
+                style,
+            } => {
+                let (paint, opacity) = self.brush(brush);
+                let element = format!(
+                    r#"<path d="{}" fill="none" stroke="{paint}" stroke-opacity="{opacity}" stroke-width="{width}"{}/>"#,
+                    path_data(shape),
+                    stroke_attributes(style)
+                );
+                self.element(Some(self.state.transform), self.state.clip, &element);
+            }
+            Command::Clip { shape } => {
+                // clip paths intersect with the clip path they are clipped by themselves
+                let id = self.id();
+                let _ = write!(self.defs, r#"<clipPath id="clip{id}""#);
+                if let Some(clip) = self.state.clip {
+                    let _ = write!(self.defs, r#" clip-path="url(#clip{clip})""#);
+                }
+                let _ = writeln!(
+                    self.defs,
+                    r#"><path d="{}"{}/></clipPath>"#,
+                    path_data(shape),
+                    transform_attribute(self.state.transform)
+                );
+                self.state.clip = Some(id);
+            }
+            Command::DrawText { layout, position } => {
+                let layout = RecordedTextLayout::new(layout.clone());
+                let element = text(&layout);
+                let transform = self.state.transform * Affine::translate(position.to_vec2());
+                self.element(Some(transform), self.state.clip, &element);
+            }
+            Command::Save => self.saved.push(self.state),
+            Command::Restore => {
+                if let Some(state) = self.saved.pop() {
+                    self.state = state;
+                }
+            }
+            Command::Transform(transform) => self.state.transform *= *transform,
+            Command::DrawImage {
+                image,
+                dst_rect,
+                interpolation,
+            }
+            | Command::DrawImageArea {
+                image,
+                dst_rect,
+                interpolation,
+                ..
+            } => {
+                let src_rect = match command {
+                    Command::DrawImageArea { src_rect, .. } => *src_rect,
+                    _ => self
+                        .image_sizes
+                        .get(*image)
+                        .copied()
+                        .unwrap_or_default()
+                        .to_rect(),
+                };
+                let rendering = match interpolation {
+                    super::Interpolation::NearestNeighbor => "optimizeSpeed",
+                    super::Interpolation::Bilinear => "optimizeQuality",
+                };
+
+                // the viewbox crops the image to the source area and scales it to the destination
+                let element = format!(
+                    r##"<svg x="{}" y="{}" width="{}" height="{}" viewBox="{} {} {} {}" preserveAspectRatio="none"><use xlink:href="#image{image}" image-rendering="{rendering}"/></svg>"##,
+                    dst_rect.x0,
+                    dst_rect.y0,
+                    dst_rect.width(),
+                    dst_rect.height(),
+                    src_rect.x0,
+                    src_rect.y0,
+                    src_rect.width(),
+                    src_rect.height()
+                );
+                self.element(Some(self.state.transform), self.state.clip, &element);
+            }
+            Command::CaptureImageArea { .. } => {}
+            Command::BlurredRect {
+                rect,
+                blur_radius,
+                brush,
+            } => {
+                let id = self.id();
+                let bounds = rect.inflate(blur_radius * 3.0, blur_radius * 3.0);
+                let _ = writeln!(
+                    self.defs,
+                    r#"<filter id="blur{id}" filterUnits="userSpaceOnUse" x="{}" y="{}" width="{}" height="{}"><feGaussianBlur stdDeviation="{blur_radius}"/></filter>"#,
+                    bounds.x0,
+                    bounds.y0,
+                    bounds.width(),
+                    bounds.height()
+                );
+
+                let (paint, opacity) = self.brush(brush);
+                let element = format!(
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{paint}" fill-opacity="{opacity}" filter="url(#blur{id})"/>"#,
+                    rect.x0,
+                    rect.y0,
+                    rect.width(),
+                    rect.height()
+                );
+                self.element(Some(self.state.transform), self.state.clip, &element);
+            }
+        }
+    }
+
+    fn fill(&mut self, shape: &Shape, brush: &Brush, fill_rule: &str) {
+        let (paint, opacity) = self.brush(brush);
+        let element = format!(
+            r#"<path d="{}" fill="{paint}" fill-opacity="{opacity}" fill-rule="{fill_rule}"/>"#,
+            path_data(shape)
+        );
+        self.element(Some(self.state.transform), self.state.clip, &element);
+    }
+
+    /// Adds `element` to the body, clipped and transformed. The clip is applied by a group, so
+    /// it isn't affected by the transform of the element.
+    fn element(&mut self, transform: Option<Affine>, clip: Option<usize>, element: &str) {
+        if let Some(clip) = clip {
+            let _ = write!(self.body, r#"<g clip-path="url(#clip{clip})">"#);
+        }
+
+        match transform.filter(|transform| *transform != Affine::IDENTITY) {
+            Some(transform) => {
+                let _ = write!(
+                    self.body,
+                    "<g{}>{element}</g>",
+                    transform_attribute(transform)
+                );
+            }
+            None => self.body.push_str(element),
+        }
+
+        if clip.is_some() {
+            self.body.push_str("</g>");
+        }
+        self.body.push('\n');
+    }
+
+    /// Paint and opacity of `brush`, gradients are added to the definitions.
+    fn brush(&mut self, brush: &Brush) -> (String, f64) {
+        let (element, attributes, stops) = match brush {
+            Brush::Solid(color) => return paint(color),
+            Brush::LinearGradient { start, end, stops } => (
+                "linearGradient",
+                format!(
+                    r#"x1="{}" y1="{}" x2="{}" y2="{}""#,
+                    start.x, start.y, end.x, end.y
+                ),
+                stops,
+            ),
+            Brush::RadialGradient {
+                center,
+                origin_offset,
+                radius,
+                stops,
+            } => {
+                let focus = *center + *origin_offset;
+                (
+                    "radialGradient",
+                    format!(
+                        r#"cx="{}" cy="{}" r="{radius}" fx="{}" fy="{}""#,
+                        center.x, center.y, focus.x, focus.y
+                    ),
+                    stops,
+                )
+            }
+        };
+
+        // coordinates of gradients are in the space of the elements they fill
+        let id = self.id();
+        let _ = write!(
+            self.defs,
+            r#"<{element} id="gradient{id}" gradientUnits="userSpaceOnUse" {attributes}>"#
+        );
+        for stop in stops {
+            let (color, opacity) = paint(&stop.color);
+            let _ = write!(
+                self.defs,
+                r#"<stop offset="{}" stop-color="{color}" stop-opacity="{opacity}"/>"#,
+                stop.pos
+            );
+        }
+        let _ = writeln!(self.defs, "</{element}>");
+
+        (format!("url(#gradient{id})"), 1.0)
+    }
+
+    fn define_image(&mut self, index: usize, image: &ImageData) -> Result<()> {
+        let mut png = Vec::new();
+        rgba_image(image)?.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)?;
+        self.image_sizes
+            .push(Size::new(image.width as f64, image.height as f64));
+
+        let _ = writeln!(
+            self.defs,
+            r#"<image id="image{index}" width="{}" height="{}" preserveAspectRatio="none" xlink:href="data:image/png;base64,{}"/>"#,
+            image.width,
+            image.height,
+            base64::engine::general_purpose::STANDARD.encode(png)
+        );
+        Ok(())
+    }
+
+    fn finish(self, size: Size) -> String {
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{width}" height="{height}" viewBox="0 0 {width} {height}">
+<defs>
+{}</defs>
+{}</svg>
+"#,
+            self.defs,
+            self.body,
+            width = size.width,
+            height = size.height
+        )
+    }
+}
+
+/// Hex color and opacity of `color`.
+fn paint(color: &Color) -> (String, f64) {
+    let (r, g, b, a) = color.as_rgba8();
+    (format!("#{r:02x}{g:02x}{b:02x}"), a as f64 / 255.0)
+}
+
+fn path_data(shape: &Shape) -> String {
+    let path = match shape {
+        Shape::Rect(rect) => rect.to_path(0.1),
+        Shape::RoundedRect(rect) => rect.to_path(0.1),
+        Shape::Circle(circle) => circle.to_path(0.1),
+        Shape::Line(line) => line.to_path(0.1),
+        Shape::Path(path) => path.clone(),
+    };
+    path.to_svg()
+}
+
+fn transform_attribute(transform: Affine) -> String {
+    if transform == Affine::IDENTITY {
+        return String::new();
+    }
+
+    let [a, b, c, d, e, f] = transform.as_coeffs();
+    format!(r#" transform="matrix({a} {b} {c} {d} {e} {f})""#)
+}
+
+fn stroke_attributes(style: &StrokeStyle) -> String {
+    let mut attributes = String::new();
+
+    match style.line_join {
+        LineJoin::Miter { limit } => {
+            let _ = write!(attributes, r#" stroke-miterlimit="{limit}""#);
+        }
+        LineJoin::Round => attributes.push_str(r#" stroke-linejoin="round""#),
+        LineJoin::Bevel => attributes.push_str(r#" stroke-linejoin="bevel""#),
+    }
+    match style.line_cap {
+        LineCap::Butt => {}
+        LineCap::Round => attributes.push_str(r#" stroke-linecap="round""#),
+        LineCap::Square => attributes.push_str(r#" stroke-linecap="square""#),
+    }
+
+    if !style.dash_pattern.is_empty() {
+        let dashes = style
+            .dash_pattern
+            .iter()
+            .map(|dash| dash.to_string())
+            .collect::<Vec<_>>();
+        let _ = write!(
+            attributes,
+            r#" stroke-dasharray="{}" stroke-dashoffset="{}""#,
+            dashes.join(" "),
+            style.dash_offset
+        );
+    }
+
+    attributes
+}
+
+/// A text element per line of `layout` with its origin at the top left corner of the layout,
+/// and a span for every run of text with the same attributes.
+fn text(layout: &RecordedTextLayout) -> String {
+    let data = &layout.data;
+    let width = data.max_width.unwrap_or_else(|| layout.size().width);
+    let (anchor, x) = match data.alignment {
+        None | Some(TextAlignment::Start) | Some(TextAlignment::Justified) => ("start", 0.0),
+        Some(TextAlignment::Center) => ("middle", width / 2.0),
+        Some(TextAlignment::End) => ("end", width),
+    };
+
+    let mut element = String::new();
+    let metrics = (0..layout.line_count()).map_while(|line_number| layout.line_metric(line_number));
+    for metric in metrics {
+        let line = metric.start_offset..metric.end_offset;
+        let _ = write!(
+            element,
+            r#"<text x="{x}" y="{}" text-anchor="{anchor}" xml:space="preserve">"#,
+            metric.y_offset + metric.baseline
+        );
+
+        for run in runs(layout, line) {
+            let Some(text) = data.text.get(run.clone()) else {
+                continue;
+            };
+            let text = text.trim_end_matches('\n');
+            let _ = write!(
+                element,
+                "<tspan{}>{}</tspan>",
+                text_attributes(&attributes_at(layout, run.start)),
+                escape(text)
+            );
+        }
+        element.push_str("</text>");
+    }
+
+    element
+}
+
+/// Ranges of `line` in which the attributes don't change.
+///
+/// Attribute ranges aren't checked when recording or deserializing, boundaries inside a
+/// character are left out and the attributes start or end with the character.
+fn runs(layout: &RecordedTextLayout, line: Range<usize>) -> Vec<Range<usize>> {
+    let text = &layout.data.text;
+    let mut boundaries = vec![line.start, line.end];
+    for (range, _) in &layout.data.range_attributes {
+        boundaries.extend([range.start, range.end]);
+    }
+    boundaries.retain(|boundary| {
+        (line.contains(boundary) || *boundary == line.end) && text.is_char_boundary(*boundary)
+    });
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    boundaries.windows(2).map(|pair| pair[0]..pair[1]).collect()
+}
+
+/// Attributes of the text at byte `index`, later ones override earlier ones of the same kind.
+fn attributes_at(layout: &RecordedTextLayout, index: usize) -> Vec<&TextAttribute> {
+    let data = &layout.data;
+    data.default_attributes
+        .iter()
+        .chain(
+            data.range_attributes
+                .iter()
+                .filter(|(range, _)| range.contains(&index))
+                .map(|(_, attribute)| attribute),
+        )
+        .collect()
+}
+
+fn text_attributes(attributes: &[&TextAttribute]) -> String {
+    let mut font_family = None;
+    let mut font_size = None;
+    let mut font_weight = None;
+    let mut color = None;
+    let mut italic = false;
+    let mut underline = false;
+    let mut strikethrough = false;
+
+    for attribute in attributes {
+        match attribute {
+            TextAttribute::FontFamily(family) => font_family = Some(family),
+            TextAttribute::FontSize(size) => font_size = Some(*size),
+            TextAttribute::Weight(weight) => font_weight = Some(*weight),
+            TextAttribute::TextColor(text_color) => color = Some(text_color),
+            TextAttribute::Italic(value) => italic = *value,
+            TextAttribute::Underline(value) => underline = *value,
+            TextAttribute::Strikethrough(value) => strikethrough = *value,
+        }
+    }
+
+    let mut result = String::new();
+    if let Some(family) = font_family {
+        let _ = write!(result, r#" font-family="{}""#, escape(family));
+    }
+    let _ = write!(
+        result,
+        r#" font-size="{}""#,
+        font_size.unwrap_or(DEFAULT_FONT_SIZE)
+    );
+    if let Some(weight) = font_weight {
+        let _ = write!(result, r#" font-weight="{weight}""#);
+    }
+    let (fill, opacity) = paint(color.unwrap_or(&Color::BLACK));
+    let _ = write!(result, r#" fill="{fill}" fill-opacity="{opacity}""#);
+    if italic {
+        result.push_str(r#" font-style="italic""#);
+    }
+
+    let decorations = [(underline, "underline"), (strikethrough, "line-through")]
+        .into_iter()
+        .filter_map(|(enabled, decoration)| enabled.then_some(decoration))
+        .collect::<Vec<_>>();
+    if !decorations.is_empty() {
+        let _ = write!(result, r#" text-decoration="{}""#, decorations.join(" "));
+    }
+
+    result
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// `image` with separate alpha, as png stores it.
+fn rgba_image(image: &ImageData) -> Result<RgbaImage> {
+    let pixels = image
+        .pixels
+        .chunks(image.format.bytes_per_pixel())
+        .flat_map(|pixel| match image.format {
+            ImageFormat::Grayscale => [pixel[0], pixel[0], pixel[0], 255],
+            ImageFormat::Rgb => [pixel[0], pixel[1], pixel[2], 255],
+            ImageFormat::RgbaSeparate => [pixel[0], pixel[1], pixel[2], pixel[3]],
+            ImageFormat::RgbaPremul => {
+                let alpha = pixel[3];
+                let unpremultiply = |channel: u8| match alpha {
+                    0 => 0,
+                    _ => ((channel as u32 * 255 + alpha as u32 / 2) / alpha as u32).min(255) as u8,
+                };
+                [
+                    unpremultiply(pixel[0]),
+                    unpremultiply(pixel[1]),
+                    unpremultiply(pixel[2]),
+                    alpha,
+                ]
+            }
+        })
+        .collect();
+
+    RgbaImage::from_raw(image.width as u32, image.height as u32, pixels).ok_or_else(|| {
+        ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::DimensionMismatch,
+        ))
+        .into()
+    })
+}
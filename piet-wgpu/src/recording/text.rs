@@ -0,0 +1,212 @@
+use std::ops::Range;
+
+use piet::{
+    kurbo::{Point, Rect, Size},
+    Error, FontFamily, HitTestPoint, HitTestPosition, LineMetric, TextAlignment, TextAttribute,
+    TextLayout, TextStorage,
+};
+
+use super::list::{font_family, TextLayoutData};
+
+// piet's default font size
+pub(super) const DEFAULT_FONT_SIZE: f64 = 12.0;
+
+// metrics relative to the font size, about those of a typical sans-serif font
+const ADVANCE: f64 = 0.5;
+const LINE_HEIGHT: f64 = 1.2;
+const BASELINE: f64 = 0.95;
+
+/// Text of the [`Recorder`](super::Recorder). There are no fonts to lay out with, layouts have
+/// fixed-width glyphs half as wide as the font size and wrap only at line breaks.
+#[derive(Clone, Default)]
+pub struct RecordedText;
+
+impl piet::Text for RecordedText {
+    type TextLayoutBuilder = RecordedTextLayoutBuilder;
+
+    type TextLayout = RecordedTextLayout;
+
+    fn font_family(&mut self, family_name: &str) -> Option<FontFamily> {
+        Some(font_family(family_name))
+    }
+
+    fn load_font(&mut self, _data: &[u8]) -> Result<FontFamily, Error> {
+        Err(Error::NotSupported)
+    }
+
+    fn new_text_layout(&mut self, text: impl TextStorage) -> Self::TextLayoutBuilder {
+        RecordedTextLayoutBuilder(TextLayoutData {
+            text: text.as_str().to_string(),
+            ..Default::default()
+        })
+    }
+}
+
+pub struct RecordedTextLayoutBuilder(TextLayoutData);
+
+impl piet::TextLayoutBuilder for RecordedTextLayoutBuilder {
+    type Out = RecordedTextLayout;
+
+    fn max_width(mut self, width: f64) -> Self {
+        self.0.max_width = Some(width).filter(|width| width.is_finite());
+        self
+    }
+
+    fn alignment(mut self, alignment: TextAlignment) -> Self {
+        self.0.alignment = Some(alignment.into());
+        self
+    }
+
+    fn default_attribute(mut self, attribute: impl Into<TextAttribute>) -> Self {
+        self.0.default_attributes.push(attribute.into().into());
+        self
+    }
+
+    fn range_attribute(
+        mut self,
+        range: impl std::ops::RangeBounds<usize>,
+        attribute: impl Into<TextAttribute>,
+    ) -> Self {
+        self.0.push_range_attribute(range, attribute);
+        self
+    }
+
+    fn build(self) -> Result<Self::Out, Error> {
+        Ok(RecordedTextLayout::new(self.0))
+    }
+}
+
+#[derive(Clone)]
+pub struct RecordedTextLayout {
+    pub(super) data: TextLayoutData,
+    font_size: f64,
+    /// Byte ranges of the lines, including the line break.
+    lines: Vec<Range<usize>>,
+}
+
+impl RecordedTextLayout {
+    pub(super) fn new(data: TextLayoutData) -> Self {
+        let font_size = data
+            .default_attributes
+            .iter()
+            .rev()
+            .find_map(|attribute| match attribute {
+                super::TextAttribute::FontSize(size) => Some(*size),
+                _ => None,
+            })
+            .unwrap_or(DEFAULT_FONT_SIZE);
+
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for (index, _) in data.text.match_indices('\n') {
+            lines.push(start..index + 1);
+            start = index + 1;
+        }
+        lines.push(start..data.text.len());
+
+        Self {
+            data,
+            font_size,
+            lines,
+        }
+    }
+
+    fn advance(&self) -> f64 {
+        self.font_size * ADVANCE
+    }
+
+    fn line_height(&self) -> f64 {
+        self.font_size * LINE_HEIGHT
+    }
+
+    fn width(&self, text: &str) -> f64 {
+        text.chars().count() as f64 * self.advance()
+    }
+}
+
+impl TextLayout for RecordedTextLayout {
+    fn size(&self) -> Size {
+        let width = self
+            .lines
+            .iter()
+            .map(|line| self.width(self.data.text[line.clone()].trim_end()))
+            .fold(0.0, f64::max);
+
+        Size::new(width, self.lines.len() as f64 * self.line_height())
+    }
+
+    fn trailing_whitespace_width(&self) -> f64 {
+        self.lines
+            .iter()
+            .map(|line| self.width(self.data.text[line.clone()].trim_end_matches('\n')))
+            .fold(0.0, f64::max)
+    }
+
+    fn image_bounds(&self) -> Rect {
+        self.size().to_rect()
+    }
+
+    fn text(&self) -> &str {
+        &self.data.text
+    }
+
+    fn line_text(&self, line_number: usize) -> Option<&str> {
+        let line = self.lines.get(line_number)?;
+        Some(&self.data.text[line.clone()])
+    }
+
+    fn line_metric(&self, line_number: usize) -> Option<LineMetric> {
+        let line = self.lines.get(line_number)?;
+        let text = &self.data.text[line.clone()];
+
+        Some(LineMetric {
+            start_offset: line.start,
+            end_offset: line.end,
+            trailing_whitespace: text.len() - text.trim_end().len(),
+            baseline: self.font_size * BASELINE,
+            height: self.line_height(),
+            y_offset: line_number as f64 * self.line_height(),
+        })
+    }
+
+    fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    fn hit_test_point(&self, point: Point) -> HitTestPoint {
+        let line_number =
+            ((point.y / self.line_height()).max(0.0) as usize).min(self.lines.len() - 1);
+        let line = &self.lines[line_number];
+        let text = self.data.text[line.clone()].trim_end_matches('\n');
+
+        let column = (point.x / self.advance()).round().max(0.0) as usize;
+        let idx = text
+            .char_indices()
+            .nth(column)
+            .map_or(text.len(), |(index, _)| index);
+
+        let is_inside = point.y >= 0.0
+            && point.y < self.size().height
+            && point.x >= 0.0
+            && point.x < self.width(text);
+
+        HitTestPoint::new(line.start + idx, is_inside)
+    }
+
+    fn hit_test_text_position(&self, idx: usize) -> HitTestPosition {
+        let idx = idx.min(self.data.text.len());
+        let line_number = self
+            .lines
+            .iter()
+            .position(|line| idx < line.end)
+            .unwrap_or(self.lines.len() - 1);
+        let line = &self.lines[line_number];
+        let before = self.data.text.get(line.start..idx).unwrap_or_default();
+
+        let point = Point::new(
+            self.width(before),
+            line_number as f64 * self.line_height() + self.font_size * BASELINE,
+        );
+        HitTestPosition::new(point, line_number)
+    }
+}
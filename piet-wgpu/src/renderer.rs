@@ -1,27 +1,44 @@
-use lyon::{
-    lyon_tessellation::{BuffersBuilder, FillOptions, FillTessellator, VertexBuffers},
-    math::point,
-    path::Path,
-};
-use piet::{kurbo::Rect, IntoBrush};
-use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
-use wgpu::util::DeviceExt;
-
-use crate::{
-    data::{Primitive, Vertex, VertexBuilder},
-    error::Result,
-    PietWgpu, WgpuBrush, WgpuImage,
-};
+use lyon::{path::Path, tessellation::StrokeOptions};
+
+use crate::{error::Result, layer::Layer, WgpuBrush, WgpuImage};
 
 pub trait WgpuRenderer {
     type Renderer: WgpuRenderer;
 
     fn set_size(&mut self, width: u32, height: u32);
     fn set_scale(&mut self, scale_factor: f64);
-    fn fill_rect(&mut self, rect: kurbo::Rect, brush: &WgpuBrush);
-    fn draw_image(&mut self, rect: kurbo::Rect, image: &WgpuImage);
-    fn clear_all(&mut self, color: wgpu::Color);
-    fn finish(&mut self) -> Result<()>;
+    fn fill(&mut self, path: Path, brush: &WgpuBrush) -> Result<()>;
+    fn stroke(&mut self, path: Path, options: &StrokeOptions, brush: &WgpuBrush) -> Result<()>;
+    fn draw_image(&mut self, rect: kurbo::Rect, image: &WgpuImage) -> Result<()>;
+    fn clear_all(&mut self, color: wgpu::Color) -> Result<()>;
+    fn clear_rect(&mut self, rect: kurbo::Rect, color: wgpu::Color) -> Result<()>;
+    /// Draws into `layer`, nested in the current layer, until `pop_layer`.
+    fn push_layer(&mut self, layer: Layer) -> Result<()>;
+    /// Goes back to the layer `layer` was pushed into, fails if no layer is open.
+    fn pop_layer(&mut self) -> Result<()>;
+    /// Starts a new frame.
+    ///
+    /// Immediate renderers drop everything drawn in the previous frame and reset per-frame state
+    /// (geometry, primitives, the clear color), retained ones keep their scene. Gpu buffers,
+    /// pipelines and images already in the atlas are kept for the frames to come either way.
+    fn begin_frame(&mut self);
+    /// Renders everything drawn since `begin_frame` and presents it.
+    fn end_frame(&mut self) -> Result<()>;
+    /// Ends the frame like `end_frame`, but only uploads it with `encoder` for `paint`.
+    ///
+    /// Uploads recorded while drawing are submitted to `queue` right away, the caller has to
+    /// submit `encoder` before the next frame is prepared.
+    fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+    ) -> Result<()>;
+    /// Draws the prepared frame into a render pass of the caller. The pass needs a single color
+    /// attachment of the renderer's target format.
+    fn paint<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>);
+    /// Renders the last finished frame again and reads it back.
+    fn screenshot(&mut self) -> Result<image::RgbaImage>;
 }
 
 // let globals_buffer_byte_size = std::mem::size_of::<Globals>() as u64;
@@ -0,0 +1,577 @@
+use std::{collections::HashMap, sync::Arc};
+
+use lyon::{
+    lyon_tessellation::{StrokeOptions, VertexBuffers},
+    path::Path,
+};
+use piet::kurbo::Rect;
+use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
+
+use crate::{
+    config::Config,
+    data::{Primitive, Vertex},
+    error::{PietWgpuError, Result},
+    geometry::{rect_path, tesselate_fill, tesselate_stroke},
+    gpu::{chunk_relative, push_draw_call, push_index, DrawCall, GpuState},
+    layer::{Layer, LayerStack},
+    renderer::WgpuRenderer,
+    shader::PrimitiveBinding,
+    PietWgpu, WgpuBrush, WgpuImage,
+};
+
+pub type RetainedRenderer = PietWgpu<WgpuRetainedRenderer>;
+
+pub type NodeId = u64;
+
+/// Tessellated content of a node, indexed relative to the node itself.
+struct Node {
+    id: NodeId,
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    primitives: Vec<Primitive>,
+    // images drawn by the primitive at each index, to place them again when the atlas is rebuilt
+    images: Vec<(u32, WgpuImage)>,
+    // layers pushed while drawing the node, primitives refer to them by their index in the node
+    layers: LayerStack,
+    // where the node starts in the gpu buffers and among the layers of all nodes
+    vertex_offset: u32,
+    index_offset: u32,
+    prim_offset: u32,
+    layer_offset: u32,
+    uploaded: bool,
+    // built when the node is uploaded, they only change along with the node's offsets
+    draw_calls: Vec<DrawCall>,
+}
+
+impl Node {
+    fn new(id: NodeId) -> Self {
+        Self {
+            id,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            primitives: Vec::new(),
+            images: Vec::new(),
+            layers: LayerStack::new(),
+            vertex_offset: 0,
+            index_offset: 0,
+            prim_offset: 0,
+            layer_offset: 0,
+            uploaded: false,
+            draw_calls: Vec::new(),
+        }
+    }
+
+    /// Layer `layer` of the node among the layers of all nodes, the root is shared.
+    fn global_layer(&self, layer: u32) -> u32 {
+        match layer {
+            0 => 0,
+            layer => layer + self.layer_offset,
+        }
+    }
+
+    fn build_draw_calls(&mut self, prim_binding: PrimitiveBinding) {
+        let mut draw_calls = std::mem::take(&mut self.draw_calls);
+        draw_calls.clear();
+
+        for (position, index) in self.indices.iter().enumerate() {
+            let prim_index = self.vertices[*index as usize].prim_index;
+            let primitive = &self.primitives[prim_index as usize];
+            push_index(
+                &mut draw_calls,
+                self.index_offset + position as u32,
+                (
+                    self.prim_offset + prim_index,
+                    &Primitive {
+                        layer: self.global_layer(primitive.layer),
+                        ..*primitive
+                    },
+                ),
+                prim_binding,
+            );
+        }
+
+        self.draw_calls = draw_calls;
+    }
+
+    fn same_content(&self, other: &Node) -> bool {
+        bytemuck::cast_slice::<_, u8>(&self.vertices)
+            == bytemuck::cast_slice::<_, u8>(&other.vertices)
+            && self.indices == other.indices
+            && bytemuck::cast_slice::<_, u8>(&self.primitives)
+                == bytemuck::cast_slice::<_, u8>(&other.primitives)
+            && self.layers.layers == other.layers.layers
+    }
+}
+
+/// Keeps tessellated geometry in nodes between frames and only uploads nodes that changed.
+///
+/// Draw calls go into the node opened with `begin_node` (or `PietWgpu::node`), replacing its
+/// previous content. Nodes are drawn in the order they were first added and stay in the scene
+/// until they are removed, frames only render what is there. Redrawing a node with the same
+/// content doesn't upload anything; if its size changes the nodes behind it move and are uploaded
+/// again as well. Each node keeps its draw calls, they are only built again for uploaded nodes.
+///
+/// Indices are always `u32` so nodes can be rewritten in place. When the image atlas runs full,
+/// it is rebuilt from the images of the nodes still in the scene and every node is uploaded again.
+pub struct WgpuRetainedRenderer {
+    gpu: GpuState,
+    nodes: Vec<Node>,
+    node_positions: HashMap<NodeId, usize>,
+    current: Option<Node>,
+    draw_calls: Vec<DrawCall>,
+    // layers of all nodes, numbered through
+    layers: Vec<Layer>,
+    uploaded_nodes: usize,
+}
+
+static_assertions::assert_impl_all!(WgpuRetainedRenderer: Send, Sync);
+
+impl WgpuRetainedRenderer {
+    pub fn new<W: HasRawWindowHandle + HasRawDisplayHandle>(
+        window: &W,
+        width: u32,
+        height: u32,
+        scale: f64,
+    ) -> Result<Self> {
+        Self::from_config(window, width, height, scale, Default::default())
+    }
+
+    pub fn from_config<W: HasRawWindowHandle + HasRawDisplayHandle>(
+        window: &W,
+        width: u32,
+        height: u32,
+        scale: f64,
+        config: Config,
+    ) -> Result<Self> {
+        GpuState::from_window(window, width, height, scale, config).map(Self::from_gpu)
+    }
+
+    /// Creates a renderer drawing into an offscreen texture instead of a window.
+    pub fn headless(width: u32, height: u32, scale: f64, config: Config) -> Result<Self> {
+        GpuState::headless(width, height, scale, config).map(Self::from_gpu)
+    }
+
+    /// Creates a renderer drawing with a device and queue of the caller.
+    ///
+    /// Frames are rendered into the view passed to `set_target_view` before each `finish`, which
+    /// has to be of `format`. The view keeps its content unless it is cleared. `finish` submits
+    /// its own command buffer to `queue`, after whatever the caller submitted before.
+    pub fn from_device(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        scale: f64,
+        config: Config,
+    ) -> Result<Self> {
+        GpuState::from_device(device, queue, format, width, height, scale, config)
+            .map(Self::from_gpu)
+    }
+
+    /// Sets the view the next frame is rendered to, for renderers created with `from_device`.
+    pub fn set_target_view(&mut self, view: wgpu::TextureView) {
+        self.gpu.set_target_view(view);
+    }
+
+    fn from_gpu(gpu: GpuState) -> Self {
+        Self {
+            gpu,
+            nodes: Vec::new(),
+            node_positions: HashMap::new(),
+            current: None,
+            draw_calls: Vec::new(),
+            layers: vec![Layer::ROOT],
+            uploaded_nodes: 0,
+        }
+    }
+
+    /// Starts drawing node `id`, closing the node drawn before.
+    pub fn begin_node(&mut self, id: NodeId) {
+        self.end_node();
+        self.current = Some(Node::new(id));
+    }
+
+    /// Replaces the content of the node drawn since `begin_node`, new nodes are added on top.
+    pub fn end_node(&mut self) {
+        let node = match self.current.take() {
+            Some(node) => node,
+            None => return,
+        };
+
+        match self.node_positions.get(&node.id) {
+            Some(position) => {
+                let old = &mut self.nodes[*position];
+
+                if !old.same_content(&node) {
+                    old.vertices = node.vertices;
+                    old.indices = node.indices;
+                    old.primitives = node.primitives;
+                    old.images = node.images;
+                    old.layers = node.layers;
+                    old.uploaded = false;
+                }
+            }
+            None => {
+                self.node_positions.insert(node.id, self.nodes.len());
+                self.nodes.push(node);
+            }
+        }
+    }
+
+    /// Removes a node from the scene, returns whether it existed.
+    pub fn remove_node(&mut self, id: NodeId) -> bool {
+        match self.node_positions.remove(&id) {
+            Some(position) => {
+                self.nodes.remove(position);
+
+                for (position, node) in self.nodes.iter().enumerate().skip(position) {
+                    self.node_positions.insert(node.id, position);
+                }
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn contains_node(&self, id: NodeId) -> bool {
+        self.node_positions.contains_key(&id)
+    }
+
+    /// Number of nodes uploaded for the last frame.
+    pub fn uploaded_nodes(&self) -> usize {
+        self.uploaded_nodes
+    }
+
+    /// Number of gpu buffers allocated so far, including staging buffers and buffers replaced
+    /// by larger ones.
+    pub fn gpu_allocations(&self) -> u64 {
+        self.gpu.allocations()
+    }
+
+    fn current_node(&mut self) -> Result<&mut Node> {
+        self.current.as_mut().ok_or(PietWgpuError::NoCurrentNode)
+    }
+
+    fn append(&mut self, geometry: VertexBuffers<Vertex, u32>, primitive: Primitive) -> Result<()> {
+        let node = self.current_node()?;
+        let offset = node.vertices.len() as u32;
+
+        node.vertices.extend_from_slice(&geometry.vertices);
+        node.indices
+            .extend(geometry.indices.iter().map(|index| *index + offset));
+        node.primitives.push(Primitive {
+            layer: node.layers.current(),
+            ..primitive
+        });
+
+        Ok(())
+    }
+
+    /// Texture coordinates of `image`, rebuilding the atlas if it doesn't fit anymore.
+    fn atlas_coords(&mut self, image: &WgpuImage) -> Result<[f32; 4]> {
+        match self.gpu.atlas_coords(image) {
+            Err(PietWgpuError::TextureBufferFull { .. }) => {
+                self.rebuild_atlas()?;
+                self.gpu.atlas_coords(image)
+            }
+            result => result,
+        }
+    }
+
+    /// Starts the atlas over with the images of the nodes in the scene, which evicts the images
+    /// of removed nodes. Their primitives get the new texture coordinates.
+    fn rebuild_atlas(&mut self) -> Result<()> {
+        self.gpu.clear_atlas();
+
+        for node in self.nodes.iter_mut().chain(self.current.as_mut()) {
+            for (prim_index, image) in &node.images {
+                node.primitives[*prim_index as usize].tex_coords = self.gpu.atlas_coords(image)?;
+            }
+            node.uploaded = false;
+        }
+
+        Ok(())
+    }
+
+    /// Moves nodes behind resized ones and uploads every node that moved or changed.
+    fn upload_nodes(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+    ) -> Result<()> {
+        let gpu = &mut self.gpu;
+
+        // a new device starts out with empty buffers
+        if gpu.take_rebuilt() {
+            self.nodes.iter_mut().for_each(|node| node.uploaded = false);
+        }
+
+        let (mut vertices, mut indices, mut primitives, mut layers) = (0, 0, 0, 0);
+
+        for node in &mut self.nodes {
+            if (
+                node.vertex_offset,
+                node.index_offset,
+                node.prim_offset,
+                node.layer_offset,
+            ) != (vertices, indices, primitives, layers)
+            {
+                node.vertex_offset = vertices;
+                node.index_offset = indices;
+                node.prim_offset = primitives;
+                node.layer_offset = layers;
+                node.uploaded = false;
+            }
+
+            vertices += node.vertices.len() as u32;
+            indices += node.indices.len() as u32;
+            primitives += node.primitives.len() as u32;
+            layers += node.layers.layers.len() as u32 - 1;
+        }
+
+        gpu.prim_binding
+            .check_primitives(primitives as u64, &device.limits())?;
+
+        let prim_bytes = match gpu.prim_binding {
+            PrimitiveBinding::Storage => primitives as u64,
+            // the last chunk is bound completely even if it isn't full
+            PrimitiveBinding::Uniform { chunk_size } => {
+                (primitives as u64).div_ceil(chunk_size as u64) * chunk_size as u64
+            }
+        } * std::mem::size_of::<Primitive>() as u64;
+
+        let mut grown = gpu.vertex_buffer.reserve(
+            device,
+            vertices as u64 * std::mem::size_of::<Vertex>() as u64,
+        )?;
+        grown |= gpu
+            .index_buffer
+            .reserve(device, indices as u64 * std::mem::size_of::<u32>() as u64)?;
+
+        if gpu.prim_buffer.reserve(device, prim_bytes)? {
+            gpu.refresh_prim_bind_group();
+            grown = true;
+        }
+
+        // grown buffers start out empty
+        if grown {
+            self.nodes.iter_mut().for_each(|node| node.uploaded = false);
+        }
+
+        self.uploaded_nodes = 0;
+
+        for node in self.nodes.iter_mut().filter(|node| !node.uploaded) {
+            let node_vertices = node
+                .vertices
+                .iter()
+                .map(|vertex| Vertex {
+                    prim_index: vertex.prim_index + node.prim_offset,
+                    ..*vertex
+                })
+                .collect::<Vec<Vertex>>();
+            let node_indices = node
+                .indices
+                .iter()
+                .map(|index| *index + node.vertex_offset)
+                .collect::<Vec<u32>>();
+
+            gpu.vertex_buffer.write_at(
+                device,
+                &mut gpu.staging_belt,
+                encoder,
+                node.vertex_offset as u64 * std::mem::size_of::<Vertex>() as u64,
+                bytemuck::cast_slice(&chunk_relative(&node_vertices, gpu.prim_binding)),
+            );
+            gpu.index_buffer.write_at(
+                device,
+                &mut gpu.staging_belt,
+                encoder,
+                node.index_offset as u64 * std::mem::size_of::<u32>() as u64,
+                bytemuck::cast_slice(&node_indices),
+            );
+            gpu.prim_buffer.write_at(
+                device,
+                &mut gpu.staging_belt,
+                encoder,
+                node.prim_offset as u64 * std::mem::size_of::<Primitive>() as u64,
+                bytemuck::cast_slice(&node.primitives),
+            );
+
+            node.build_draw_calls(gpu.prim_binding);
+            node.uploaded = true;
+            self.uploaded_nodes += 1;
+        }
+
+        self.draw_calls.clear();
+        self.layers.truncate(1);
+
+        for node in &self.nodes {
+            self.layers
+                .extend(node.layers.layers[1..].iter().map(|layer| Layer {
+                    parent: node.global_layer(layer.parent),
+                    ..layer.clone()
+                }));
+
+            for draw_call in &node.draw_calls {
+                push_draw_call(&mut self.draw_calls, draw_call);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl WgpuRenderer for WgpuRetainedRenderer {
+    type Renderer = WgpuRetainedRenderer;
+
+    fn set_size(&mut self, width: u32, height: u32) {
+        self.gpu.resize(width, height);
+    }
+
+    fn set_scale(&mut self, scale_factor: f64) {
+        self.gpu.scale = scale_factor;
+    }
+
+    fn fill(&mut self, path: Path, brush: &WgpuBrush) -> Result<()> {
+        let prim_index = self.current_node()?.primitives.len() as u32;
+        let geometry = tesselate_fill(prim_index, path, self.gpu.feather_width())?;
+        let primitive = Primitive {
+            color: brush.color(),
+            ..Default::default()
+        };
+
+        self.append(geometry, primitive)
+    }
+
+    fn stroke(&mut self, path: Path, options: &StrokeOptions, brush: &WgpuBrush) -> Result<()> {
+        let prim_index = self.current_node()?.primitives.len() as u32;
+        let geometry = tesselate_stroke(prim_index, &path, options, self.gpu.feather_width())?;
+        let primitive = Primitive {
+            color: brush.color(),
+            ..Default::default()
+        };
+
+        self.append(geometry, primitive)
+    }
+
+    fn draw_image(&mut self, rect: Rect, image: &WgpuImage) -> Result<()> {
+        let prim_index = self.current_node()?.primitives.len() as u32;
+        let geometry = tesselate_fill(prim_index, rect_path(rect), None)?;
+
+        let primitive = Primitive {
+            lower_bound: [rect.x0 as f32, rect.y0 as f32],
+            upper_bound: [rect.x1 as f32, rect.y1 as f32],
+            tex_coords: self.atlas_coords(image)?,
+            ..Default::default()
+        };
+
+        self.current_node()?
+            .images
+            .push((prim_index, image.clone()));
+        self.append(geometry, primitive)
+    }
+
+    fn clear_all(&mut self, color: wgpu::Color) -> Result<()> {
+        // nodes are only removed explicitly, a full clear sets the background
+        self.gpu.clear_color = Some(color);
+
+        Ok(())
+    }
+
+    fn clear_rect(&mut self, rect: Rect, color: wgpu::Color) -> Result<()> {
+        let prim_index = self.current_node()?.primitives.len() as u32;
+        let geometry = tesselate_fill(prim_index, rect_path(rect), None)?;
+
+        // an untextured rect replacing the target's pixels is a region clear
+        let primitive = Primitive {
+            color: [
+                color.r as f32,
+                color.g as f32,
+                color.b as f32,
+                color.a as f32,
+            ],
+            replace: 1,
+            ..Default::default()
+        };
+
+        self.append(geometry, primitive)
+    }
+
+    fn push_layer(&mut self, layer: Layer) -> Result<()> {
+        self.current_node()?.layers.push(layer);
+        Ok(())
+    }
+
+    fn pop_layer(&mut self) -> Result<()> {
+        match self.current_node()?.layers.pop() {
+            true => Ok(()),
+            false => Err(PietWgpuError::NoOpenLayer),
+        }
+    }
+
+    fn begin_frame(&mut self) {}
+
+    fn end_frame(&mut self) -> Result<()> {
+        self.end_node();
+        self.gpu.poll();
+
+        let device = self.gpu.device.clone();
+        let mut encoder = self.gpu.take_encoder();
+        self.upload_nodes(&device, &mut encoder)?;
+
+        self.gpu.render(
+            encoder,
+            wgpu::IndexFormat::Uint32,
+            &self.draw_calls,
+            &self.layers,
+        )
+    }
+
+    fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+    ) -> Result<()> {
+        self.end_node();
+        self.gpu.poll();
+
+        let upload_encoder = self.gpu.take_encoder();
+        self.upload_nodes(device, encoder)?;
+
+        self.gpu.prepare(
+            device,
+            queue,
+            encoder,
+            upload_encoder,
+            wgpu::IndexFormat::Uint32,
+            &self.draw_calls,
+            &self.layers,
+        )
+    }
+
+    fn paint<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.gpu
+            .paint(render_pass, wgpu::IndexFormat::Uint32, &self.draw_calls);
+    }
+
+    fn screenshot(&mut self) -> Result<image::RgbaImage> {
+        self.gpu
+            .screenshot(wgpu::IndexFormat::Uint32, &self.draw_calls)
+    }
+}
+
+impl PietWgpu<WgpuRetainedRenderer> {
+    /// Draws node `id` with `draw`, replacing what the node contained before.
+    pub fn node(&mut self, id: NodeId, draw: impl FnOnce(&mut Self)) {
+        self.renderer.begin_node(id);
+        draw(self);
+        self.renderer.end_node();
+    }
+
+    pub fn remove_node(&mut self, id: NodeId) -> bool {
+        self.renderer.remove_node(id)
+    }
+}
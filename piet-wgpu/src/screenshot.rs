@@ -0,0 +1,113 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageFormat, RgbaImage};
+
+use crate::error::{PietWgpuError, Result};
+
+/// Submits `encoder` with a copy of `texture` and waits for it, the pixels are returned with
+/// separate alpha.
+///
+/// Targets either encode to sRGB or the shader does, so the texels are sRGB either way.
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mut encoder: wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+    (width, height): (u32, u32),
+    format: wgpu::TextureFormat,
+    premultiplied: bool,
+) -> Result<RgbaImage> {
+    let bgra = match format {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+        _ => return Err(PietWgpuError::UnsupportedReadbackFormat(format)),
+    };
+
+    // rows of the copy have to be aligned, the padding is dropped again when reading
+    let padded_row = (width * 4).div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+        * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size: (padded_row * height) as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(padded_row),
+                rows_per_image: None,
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver
+        .recv()
+        .map_err(|_| PietWgpuError::DeviceLost)?
+        .map_err(|_| PietWgpuError::DeviceLost)?;
+
+    let data = slice.get_mapped_range();
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    for row in data.chunks(padded_row as usize) {
+        for pixel in row[..(width * 4) as usize].chunks(4) {
+            let [r, g, b, a] = match bgra {
+                true => [pixel[2], pixel[1], pixel[0], pixel[3]],
+                false => [pixel[0], pixel[1], pixel[2], pixel[3]],
+            };
+
+            pixels.extend(match premultiplied {
+                true => [
+                    unpremultiply(r, a),
+                    unpremultiply(g, a),
+                    unpremultiply(b, a),
+                    a,
+                ],
+                false => [r, g, b, a],
+            });
+        }
+    }
+    drop(data);
+    buffer.unmap();
+
+    Ok(RgbaImage::from_raw(width, height, pixels).expect("readback has the texture's size"))
+}
+
+fn unpremultiply(channel: u8, alpha: u8) -> u8 {
+    match alpha {
+        0 => 0,
+        _ => ((channel as u32 * 255 + alpha as u32 / 2) / alpha as u32).min(255) as u8,
+    }
+}
+
+/// Encodes a screenshot to `format`, like png or jpeg. Formats without alpha, like jpeg, get
+/// the colors without it.
+pub fn encode_image(image: &RgbaImage, format: ImageFormat) -> Result<Vec<u8>> {
+    let mut encoded = Vec::new();
+    let mut writer = Cursor::new(&mut encoded);
+
+    match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgba8(image.clone())
+            .into_rgb8()
+            .write_to(&mut writer, format)?,
+        _ => image.write_to(&mut writer, format)?,
+    }
+
+    Ok(encoded)
+}
@@ -0,0 +1,333 @@
+use std::{borrow::Cow, num::NonZeroU64};
+
+use crate::{
+    data::Primitive,
+    error::{PietWgpuError, Result},
+};
+
+pub const SIMPLE_SHADER: &str = include_str!("./../shaders/simple.wgsl");
+pub const COMPOSITE_SHADER: &str = include_str!("./../shaders/composite.wgsl");
+pub const FILTER_SHADER: &str = include_str!("./../shaders/filter.wgsl");
+
+/// Declaration of the primitives in `simple.wgsl`, replaced for the uniform buffer fallback.
+const STORAGE_PRIMITIVES: &str = "var<storage, read> primitives: array<Primitive>;";
+
+const PRIM_SIZE: u64 = std::mem::size_of::<Primitive>() as u64;
+
+/// How the shaders access the primitive buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrimitiveBinding {
+    /// All primitives of a frame are bound at once as a storage buffer.
+    Storage,
+    /// Adapters without storage buffers in vertex and fragment shaders get a fixed size uniform
+    /// array, the primitives are bound `chunk_size` at a time with a dynamic offset.
+    Uniform { chunk_size: u32 },
+}
+
+impl PrimitiveBinding {
+    pub fn new(
+        adapter: Option<&wgpu::Adapter>,
+        limits: &wgpu::Limits,
+        force_uniform: bool,
+    ) -> Self {
+        // without the adapter, only the device's limits tell whether storage buffers work
+        let flags = adapter.map_or(wgpu::DownlevelFlags::all(), |adapter| {
+            adapter.get_downlevel_capabilities().flags
+        });
+        let storage_supported = flags.contains(
+            wgpu::DownlevelFlags::VERTEX_STORAGE | wgpu::DownlevelFlags::FRAGMENT_STORAGE,
+        ) && limits.max_storage_buffers_per_shader_stage > 0;
+
+        if storage_supported && !force_uniform {
+            return PrimitiveBinding::Storage;
+        }
+
+        // dynamic offsets are multiples of the chunk's byte size, so they have to be aligned
+        let alignment = limits.min_uniform_buffer_offset_alignment as u64;
+        let step = alignment / gcd(PRIM_SIZE, alignment);
+        let max_prims = limits.max_uniform_buffer_binding_size as u64 / PRIM_SIZE;
+
+        PrimitiveBinding::Uniform {
+            chunk_size: (max_prims / step * step).max(step) as u32,
+        }
+    }
+
+    pub fn shader_source(&self) -> Cow<'static, str> {
+        match self {
+            PrimitiveBinding::Storage => Cow::Borrowed(SIMPLE_SHADER),
+            PrimitiveBinding::Uniform { chunk_size } => Cow::Owned(SIMPLE_SHADER.replace(
+                STORAGE_PRIMITIVES,
+                &format!("var<uniform> primitives: array<Primitive, {chunk_size}>;"),
+            )),
+        }
+    }
+
+    pub fn binding_type(&self) -> wgpu::BindingType {
+        match self {
+            PrimitiveBinding::Storage => wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(PRIM_SIZE),
+            },
+            PrimitiveBinding::Uniform { chunk_size } => wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: true,
+                min_binding_size: NonZeroU64::new(*chunk_size as u64 * PRIM_SIZE),
+            },
+        }
+    }
+
+    pub fn buffer_usage(&self) -> wgpu::BufferUsages {
+        match self {
+            PrimitiveBinding::Storage => wgpu::BufferUsages::STORAGE,
+            PrimitiveBinding::Uniform { .. } => wgpu::BufferUsages::UNIFORM,
+        }
+    }
+
+    /// The primitive buffer has to hold at least one chunk to be bound.
+    pub fn min_buffer_size(&self) -> u64 {
+        match self {
+            PrimitiveBinding::Storage => PRIM_SIZE,
+            PrimitiveBinding::Uniform { chunk_size } => *chunk_size as u64 * PRIM_SIZE,
+        }
+    }
+
+    /// Number of primitives a frame can have, `None` if there is no limit besides the buffer's
+    /// size. Draw calls bind the whole storage buffer, so it can't be larger than one binding.
+    pub fn max_primitives(&self, limits: &wgpu::Limits) -> Option<u64> {
+        match self {
+            PrimitiveBinding::Storage => {
+                Some(limits.max_storage_buffer_binding_size as u64 / PRIM_SIZE)
+            }
+            PrimitiveBinding::Uniform { .. } => None,
+        }
+    }
+
+    /// Fails if a frame has more primitives than `max_primitives`.
+    pub fn check_primitives(&self, count: u64, limits: &wgpu::Limits) -> Result<()> {
+        match self.max_primitives(limits) {
+            Some(max) if count > max => Err(PietWgpuError::TooManyPrimitives { count, max }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Size of the buffer range visible to a draw call.
+    pub fn binding_size(&self, buffer_size: u64, limits: &wgpu::Limits) -> Option<NonZeroU64> {
+        match self {
+            PrimitiveBinding::Storage => {
+                NonZeroU64::new(buffer_size.min(limits.max_storage_buffer_binding_size as u64))
+            }
+            PrimitiveBinding::Uniform { chunk_size } => {
+                NonZeroU64::new(*chunk_size as u64 * PRIM_SIZE)
+            }
+        }
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::{offset_of, size_of};
+
+    use naga::{
+        valid::{Capabilities, ValidationFlags, Validator},
+        Handle, Module, Type, TypeInner,
+    };
+
+    use super::*;
+    use crate::{data::Globals, filter::FilterUniform, layer::LayerUniform};
+
+    /// Names and offsets of the fields of a `#[repr(C)]` struct, in declaration order.
+    macro_rules! fields {
+        ($ty:ty { $($field:ident),* $(,)? }) => {
+            vec![$((stringify!($field).to_string(), offset_of!($ty, $field) as u32)),*]
+        };
+    }
+
+    fn parse(name: &str, source: &str) -> Module {
+        let module = naga::front::wgsl::parse_str(source).unwrap_or_else(|error| {
+            panic!(
+                "{name} doesn't parse:\n{}",
+                error.emit_to_string_with_path(source, name)
+            )
+        });
+
+        Validator::new(ValidationFlags::all(), Capabilities::empty())
+            .validate(&module)
+            .unwrap_or_else(|error| panic!("{name} doesn't validate: {error:?}"));
+
+        module
+    }
+
+    fn global_type(module: &Module, name: &str) -> Handle<Type> {
+        module
+            .global_variables
+            .iter()
+            .find(|(_, global)| global.name.as_deref() == Some(name))
+            .unwrap_or_else(|| panic!("no global {name}"))
+            .1
+            .ty
+    }
+
+    fn assert_struct_layout(
+        module: &Module,
+        ty: Handle<Type>,
+        fields: Vec<(String, u32)>,
+        size: usize,
+    ) {
+        let TypeInner::Struct { members, span } = &module.types[ty].inner else {
+            panic!("{:?} isn't a struct", module.types[ty].name);
+        };
+        let members = members
+            .iter()
+            .map(|member| (member.name.clone().unwrap_or_default(), member.offset))
+            .collect::<Vec<_>>();
+
+        assert_eq!(members, fields, "offsets of {:?}", module.types[ty].name);
+        assert_eq!(*span as usize, size, "size of {:?}", module.types[ty].name);
+    }
+
+    #[test]
+    fn shaders_validate() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("shaders");
+
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let source = std::fs::read_to_string(&path).unwrap();
+            parse(&path.display().to_string(), &source);
+        }
+
+        let uniform = PrimitiveBinding::Uniform { chunk_size: 16 };
+        parse(
+            "simple.wgsl with uniform primitives",
+            &uniform.shader_source(),
+        );
+    }
+
+    #[test]
+    fn globals_layout_matches() {
+        let module = parse("simple.wgsl", SIMPLE_SHADER);
+
+        assert_struct_layout(
+            &module,
+            global_type(&module, "globals"),
+            fields!(Globals {
+                resolution,
+                scale_factor,
+                srgb_encode,
+                premultiply,
+                _pad,
+            }),
+            size_of::<Globals>(),
+        );
+    }
+
+    #[test]
+    fn primitive_layout_matches() {
+        for binding in [
+            PrimitiveBinding::Storage,
+            PrimitiveBinding::Uniform { chunk_size: 16 },
+        ] {
+            let module = parse("simple.wgsl", &binding.shader_source());
+
+            let ty = global_type(&module, "primitives");
+            let TypeInner::Array { base, stride, .. } = module.types[ty].inner else {
+                panic!("primitives aren't an array");
+            };
+            assert_eq!(
+                stride as usize,
+                size_of::<Primitive>(),
+                "stride of primitives"
+            );
+
+            assert_struct_layout(
+                &module,
+                base,
+                fields!(Primitive {
+                    lower_bound,
+                    upper_bound,
+                    color,
+                    tex_coords,
+                    translate,
+                    angle,
+                    scale,
+                    z_index,
+                    replace,
+                    layer,
+                    _pad,
+                }),
+                size_of::<Primitive>(),
+            );
+        }
+    }
+
+    #[test]
+    fn layer_layout_matches() {
+        let module = parse("composite.wgsl", COMPOSITE_SHADER);
+
+        // the padding is only declared on the Rust side
+        let fields = fields!(LayerUniform {
+            opacity,
+            blend_mode,
+            encode_srgb,
+            _pad
+        });
+        assert_struct_layout(
+            &module,
+            global_type(&module, "layer"),
+            fields[..3].to_vec(),
+            12,
+        );
+        assert_eq!(size_of::<LayerUniform>(), 16);
+    }
+
+    #[test]
+    fn filter_layout_matches() {
+        let module = parse("filter.wgsl", FILTER_SHADER);
+
+        // the shader's struct is rounded up to its alignment, the Rust side pads explicitly
+        let fields = fields!(FilterUniform {
+            color_matrix,
+            offset,
+            color,
+            direction,
+            shift,
+            sigma,
+            taps,
+            decode_srgb,
+            _pad
+        });
+        assert_struct_layout(
+            &module,
+            global_type(&module, "params"),
+            fields[..8].to_vec(),
+            size_of::<FilterUniform>(),
+        );
+    }
+
+    #[test]
+    fn storage_bindings_limit_the_primitives() {
+        let limits = wgpu::Limits {
+            max_storage_buffer_binding_size: 16 * PRIM_SIZE as u32,
+            ..wgpu::Limits::default()
+        };
+
+        assert_eq!(PrimitiveBinding::Storage.max_primitives(&limits), Some(16));
+        assert!(PrimitiveBinding::Storage
+            .check_primitives(16, &limits)
+            .is_ok());
+        assert!(matches!(
+            PrimitiveBinding::Storage.check_primitives(17, &limits),
+            Err(PietWgpuError::TooManyPrimitives { count: 17, max: 16 })
+        ));
+        // uniform chunks are bound one after the other
+        let uniform = PrimitiveBinding::Uniform { chunk_size: 4 };
+        assert!(uniform.check_primitives(1 << 20, &limits).is_ok());
+    }
+}
@@ -0,0 +1,207 @@
+use std::sync::{Arc, Mutex};
+
+use wgpu::BufferUsages;
+
+struct Chunk {
+    buffer: Arc<wgpu::Buffer>,
+    size: u64,
+    offset: u64,
+}
+
+/// Mapped buffers that uploads are copied from, recycled once the gpu is done with them.
+///
+/// Works like wgpu's `StagingBelt`, but counts how many staging buffers it had to allocate and
+/// can stage texture data as well.
+pub struct StagingBelt {
+    chunk_size: u64,
+    active_chunks: Vec<Chunk>,
+    closed_chunks: Vec<Chunk>,
+    free_chunks: Vec<Chunk>,
+    // chunks the gpu is done with, filled by map_async callbacks
+    recalled_chunks: Arc<Mutex<Vec<Chunk>>>,
+    allocations: u64,
+}
+
+impl StagingBelt {
+    pub fn new(chunk_size: u64) -> Self {
+        Self {
+            chunk_size,
+            active_chunks: Vec::new(),
+            closed_chunks: Vec::new(),
+            free_chunks: Vec::new(),
+            recalled_chunks: Default::default(),
+            allocations: 0,
+        }
+    }
+
+    /// Copies `data` into a staging buffer and records a copy to `target` at `offset`. The data is
+    /// padded to a multiple of four bytes.
+    pub fn write_buffer(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::Buffer,
+        offset: u64,
+        data: &[u8],
+    ) {
+        if data.is_empty() {
+            return;
+        }
+
+        let size = wgpu::util::align_to(data.len() as u64, wgpu::COPY_BUFFER_ALIGNMENT);
+        let (buffer, staging_offset) = self.stage(device, size, |view| {
+            view[..data.len()].copy_from_slice(data);
+            view[data.len()..].fill(0);
+        });
+
+        encoder.copy_buffer_to_buffer(&buffer, staging_offset, target, offset, size);
+    }
+
+    /// Copies tightly packed rgba8 pixels into a staging buffer and records a copy to `origin` of
+    /// `texture`. Rows are padded to wgpu's row alignment on the way.
+    pub fn write_texture(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        origin: wgpu::Origin3d,
+        size: wgpu::Extent3d,
+        pixels: &[u8],
+    ) {
+        let row_size = 4 * size.width as usize;
+        let padded_row_size =
+            wgpu::util::align_to(row_size, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize);
+
+        if row_size == 0 || size.height == 0 {
+            return;
+        }
+
+        let (buffer, staging_offset) = self.stage(
+            device,
+            (padded_row_size * size.height as usize) as u64,
+            |view| {
+                for (row, pixels) in view
+                    .chunks_mut(padded_row_size)
+                    .zip(pixels.chunks(row_size))
+                {
+                    row[..row_size].copy_from_slice(pixels);
+                }
+            },
+        );
+
+        encoder.copy_buffer_to_texture(
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: staging_offset,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_row_size as u32),
+                    rows_per_image: std::num::NonZeroU32::new(size.height),
+                },
+            },
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin,
+                aspect: wgpu::TextureAspect::All,
+            },
+            size,
+        );
+    }
+
+    /// Reserves `size` bytes in a mapped chunk and lets `write` fill them.
+    fn stage(
+        &mut self,
+        device: &wgpu::Device,
+        size: u64,
+        write: impl FnOnce(&mut [u8]),
+    ) -> (Arc<wgpu::Buffer>, u64) {
+        let mut chunk = match self
+            .active_chunks
+            .iter()
+            .position(|chunk| chunk.offset + size <= chunk.size)
+        {
+            Some(index) => self.active_chunks.swap_remove(index),
+            None => {
+                self.receive_chunks();
+
+                match self.free_chunks.iter().position(|chunk| size <= chunk.size) {
+                    Some(index) => self.free_chunks.swap_remove(index),
+                    None => self.create_chunk(device, size),
+                }
+            }
+        };
+
+        let offset = chunk.offset;
+        chunk.offset = wgpu::util::align_to(offset + size, wgpu::MAP_ALIGNMENT);
+
+        write(
+            &mut chunk
+                .buffer
+                .slice(offset..offset + size)
+                .get_mapped_range_mut(),
+        );
+
+        let buffer = chunk.buffer.clone();
+        self.active_chunks.push(chunk);
+
+        (buffer, offset)
+    }
+
+    fn create_chunk(&mut self, device: &wgpu::Device, size: u64) -> Chunk {
+        let size = self.chunk_size.max(size);
+
+        self.allocations += 1;
+
+        Chunk {
+            buffer: Arc::new(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Staging Buffer"),
+                size,
+                usage: BufferUsages::MAP_WRITE | BufferUsages::COPY_SRC,
+                mapped_at_creation: true,
+            })),
+            size,
+            offset: 0,
+        }
+    }
+
+    /// Unmaps the chunks written to, has to be called before the copies are submitted.
+    pub fn finish(&mut self) {
+        for chunk in self.active_chunks.drain(..) {
+            chunk.buffer.unmap();
+            self.closed_chunks.push(chunk);
+        }
+    }
+
+    /// Maps the submitted chunks again so they can be reused once the gpu is done with them.
+    pub fn recall(&mut self) {
+        self.receive_chunks();
+
+        for chunk in self.closed_chunks.drain(..) {
+            let recalled_chunks = self.recalled_chunks.clone();
+
+            chunk
+                .buffer
+                .clone()
+                .slice(..)
+                .map_async(wgpu::MapMode::Write, move |_| {
+                    if let Ok(mut recalled_chunks) = recalled_chunks.lock() {
+                        recalled_chunks.push(chunk);
+                    }
+                });
+        }
+    }
+
+    fn receive_chunks(&mut self) {
+        if let Ok(mut recalled_chunks) = self.recalled_chunks.lock() {
+            for mut chunk in recalled_chunks.drain(..) {
+                chunk.offset = 0;
+                self.free_chunks.push(chunk);
+            }
+        }
+    }
+
+    /// Number of staging buffers allocated so far.
+    pub fn allocations(&self) -> u64 {
+        self.allocations
+    }
+}
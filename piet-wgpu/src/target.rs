@@ -0,0 +1,242 @@
+use crate::error::{PietWgpuError, Result};
+
+/// What a renderer draws its frames into.
+pub enum RenderTarget {
+    /// The surface of a window, frames are presented after rendering.
+    Surface {
+        surface: wgpu::Surface,
+        config: wgpu::SurfaceConfiguration,
+    },
+    /// An offscreen texture for rendering without a window.
+    Texture {
+        texture: wgpu::Texture,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    },
+    /// Views handed over by the caller for every frame, usually into a texture the caller
+    /// composites further.
+    View {
+        view: Option<wgpu::TextureView>,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl RenderTarget {
+    pub fn surface(
+        device: &wgpu::Device,
+        surface: wgpu::Surface,
+        config: wgpu::SurfaceConfiguration,
+    ) -> Self {
+        surface.configure(device, &config);
+
+        RenderTarget::Surface { surface, config }
+    }
+
+    pub fn texture(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        RenderTarget::Texture {
+            texture: Self::create_texture(device, format, width, height),
+            format,
+            width,
+            height,
+        }
+    }
+
+    pub fn view(format: wgpu::TextureFormat, width: u32, height: u32) -> Self {
+        RenderTarget::View {
+            view: None,
+            format,
+            width,
+            height,
+        }
+    }
+
+    /// Sets the view the next frame is rendered to, ignored by other targets.
+    pub fn set_view(&mut self, new_view: wgpu::TextureView) {
+        if let RenderTarget::View { view, .. } = self {
+            *view = Some(new_view);
+        }
+    }
+
+    /// The window's surface, adapters have to be compatible with it.
+    pub fn window_surface(&self) -> Option<&wgpu::Surface> {
+        match self {
+            RenderTarget::Surface { surface, .. } => Some(surface),
+            RenderTarget::Texture { .. } | RenderTarget::View { .. } => None,
+        }
+    }
+
+    /// Creates the target's resources again on a new device.
+    pub fn recreate(&mut self, device: &wgpu::Device) {
+        let (width, height) = self.size();
+        self.resize(device, width, height);
+    }
+
+    /// Color frames are cleared with unless something else is drawn, views of the caller keep
+    /// their content so piet-wgpu can draw on top of it.
+    pub fn default_clear_color(&self) -> Option<wgpu::Color> {
+        match self {
+            RenderTarget::Surface { .. } | RenderTarget::Texture { .. } => Some(wgpu::Color::WHITE),
+            RenderTarget::View { .. } => None,
+        }
+    }
+
+    fn create_texture(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Target Texture"),
+            // textures can't be empty, unlike windows
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+        })
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        match self {
+            RenderTarget::Surface { config, .. } => config.format,
+            RenderTarget::Texture { format, .. } | RenderTarget::View { format, .. } => *format,
+        }
+    }
+
+    /// Format layers are rendered in. Targets storing colors as written get the sRGB variant of
+    /// their format, so blending happens in linear light for either.
+    pub fn layer_format(&self) -> wgpu::TextureFormat {
+        match self.format() {
+            wgpu::TextureFormat::Rgba8Unorm => wgpu::TextureFormat::Rgba8UnormSrgb,
+            wgpu::TextureFormat::Bgra8Unorm => wgpu::TextureFormat::Bgra8UnormSrgb,
+            format => format,
+        }
+    }
+
+    /// Whether layers encode to sRGB when written, otherwise the shader has to.
+    pub fn layers_encode_srgb(&self) -> bool {
+        self.layer_format().describe().srgb
+    }
+
+    /// Whether the window's compositor expects premultiplied colors.
+    pub fn premultiplied_alpha(&self) -> bool {
+        match self {
+            RenderTarget::Surface { config, .. } => {
+                config.alpha_mode == wgpu::CompositeAlphaMode::PreMultiplied
+            }
+            RenderTarget::Texture { .. } | RenderTarget::View { .. } => false,
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        match self {
+            RenderTarget::Surface { config, .. } => (config.width, config.height),
+            RenderTarget::Texture { width, height, .. }
+            | RenderTarget::View { width, height, .. } => (*width, *height),
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, new_width: u32, new_height: u32) {
+        match self {
+            RenderTarget::Surface { surface, config } => {
+                config.width = new_width;
+                config.height = new_height;
+
+                // minimized windows can't be configured, frames are skipped until they are restored
+                if new_width > 0 && new_height > 0 {
+                    surface.configure(device, config);
+                }
+            }
+            RenderTarget::Texture {
+                texture,
+                format,
+                width,
+                height,
+            } => {
+                *texture = Self::create_texture(device, *format, new_width, new_height);
+                *width = new_width;
+                *height = new_height;
+            }
+            RenderTarget::View { width, height, .. } => {
+                *width = new_width;
+                *height = new_height;
+            }
+        }
+    }
+
+    /// The view to render the next frame to, views of the caller are used up by it. There is no
+    /// frame if the window is minimized or the surface timed out.
+    ///
+    /// Lost and outdated surfaces are reconfigured, a surface that is still lost afterwards
+    /// usually means the device was lost.
+    pub fn next_frame(&mut self, device: &wgpu::Device) -> Result<Option<TargetFrame>> {
+        match self {
+            RenderTarget::Surface { surface, config } => {
+                if config.width == 0 || config.height == 0 {
+                    return Ok(None);
+                }
+
+                let surface_texture = match surface.get_current_texture() {
+                    Ok(surface_texture) => surface_texture,
+                    Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                        surface.configure(device, config);
+                        surface.get_current_texture()?
+                    }
+                    Err(wgpu::SurfaceError::Timeout) => {
+                        log::warn!("Timed out acquiring the next surface texture, skipping frame");
+                        return Ok(None);
+                    }
+                    Err(error) => return Err(error.into()),
+                };
+
+                let view = surface_texture
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+
+                Ok(Some(TargetFrame {
+                    view,
+                    surface_texture: Some(surface_texture),
+                }))
+            }
+            RenderTarget::Texture { texture, .. } => Ok(Some(TargetFrame {
+                view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                surface_texture: None,
+            })),
+            RenderTarget::View { view, .. } => Ok(Some(TargetFrame {
+                view: view.take().ok_or(PietWgpuError::MissingTargetView)?,
+                surface_texture: None,
+            })),
+        }
+    }
+}
+
+/// The view a single frame is rendered to.
+pub struct TargetFrame {
+    pub view: wgpu::TextureView,
+    surface_texture: Option<wgpu::SurfaceTexture>,
+}
+
+impl TargetFrame {
+    /// Shows the frame on screen if it belongs to a surface.
+    pub fn present(self) {
+        if let Some(surface_texture) = self.surface_texture {
+            surface_texture.present();
+        }
+    }
+}
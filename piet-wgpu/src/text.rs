@@ -9,15 +9,15 @@ impl piet::Text for WgpuText {
 
     type TextLayout = WgpuTextLayout;
 
-    fn font_family(&mut self, family_name: &str) -> Option<FontFamily> {
+    fn font_family(&mut self, _family_name: &str) -> Option<FontFamily> {
         todo!()
     }
 
-    fn load_font(&mut self, data: &[u8]) -> Result<FontFamily, Error> {
+    fn load_font(&mut self, _data: &[u8]) -> Result<FontFamily, Error> {
         todo!()
     }
 
-    fn new_text_layout(&mut self, text: impl TextStorage) -> Self::TextLayoutBuilder {
+    fn new_text_layout(&mut self, _text: impl TextStorage) -> Self::TextLayoutBuilder {
         todo!()
     }
 }
@@ -27,22 +27,22 @@ pub struct WgpuTextLayoutBuilder;
 impl piet::TextLayoutBuilder for WgpuTextLayoutBuilder {
     type Out = WgpuTextLayout;
 
-    fn max_width(self, width: f64) -> Self {
+    fn max_width(self, _width: f64) -> Self {
         todo!()
     }
 
-    fn alignment(self, alignment: TextAlignment) -> Self {
+    fn alignment(self, _alignment: TextAlignment) -> Self {
         todo!()
     }
 
-    fn default_attribute(self, attribute: impl Into<TextAttribute>) -> Self {
+    fn default_attribute(self, _attribute: impl Into<TextAttribute>) -> Self {
         todo!()
     }
 
     fn range_attribute(
         self,
-        range: impl std::ops::RangeBounds<usize>,
-        attribute: impl Into<TextAttribute>,
+        _range: impl std::ops::RangeBounds<usize>,
+        _attribute: impl Into<TextAttribute>,
     ) -> Self {
         todo!()
     }
@@ -72,11 +72,11 @@ impl TextLayout for WgpuTextLayout {
         todo!()
     }
 
-    fn line_text(&self, line_number: usize) -> Option<&str> {
+    fn line_text(&self, _line_number: usize) -> Option<&str> {
         todo!()
     }
 
-    fn line_metric(&self, line_number: usize) -> Option<LineMetric> {
+    fn line_metric(&self, _line_number: usize) -> Option<LineMetric> {
         todo!()
     }
 
@@ -84,11 +84,11 @@ impl TextLayout for WgpuTextLayout {
         todo!()
     }
 
-    fn hit_test_point(&self, point: kurbo::Point) -> HitTestPoint {
+    fn hit_test_point(&self, _point: kurbo::Point) -> HitTestPoint {
         todo!()
     }
 
-    fn hit_test_text_position(&self, idx: usize) -> HitTestPosition {
+    fn hit_test_text_position(&self, _idx: usize) -> HitTestPosition {
         todo!()
     }
 }
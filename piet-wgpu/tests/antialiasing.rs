@@ -0,0 +1,71 @@
+//! Smoothing edges without multisampling.
+
+#[macro_use]
+mod common;
+
+use common::Gpu;
+use image::RgbaImage;
+use piet_wgpu::{
+    immediate::WgpuImmediateRenderer, AntiAliasing, Circle, Color, Config, Line, PietWgpu,
+    RenderContext,
+};
+
+const SIZE: u32 = 32;
+
+/// What `draw` draws in black on white, with feathered edges and a single sample.
+fn render_feathered(
+    gpu: &Gpu,
+    draw: impl FnOnce(&mut PietWgpu<WgpuImmediateRenderer>),
+) -> RgbaImage {
+    let config = Config {
+        anti_aliasing: AntiAliasing::Edges,
+        sample_count: 1,
+        ..Config::default()
+    };
+    let (mut piet, texture) = gpu.piet_with_config(SIZE, SIZE, config);
+
+    piet.clear(None, Color::WHITE);
+    draw(&mut piet);
+    piet.finish().expect("frame renders");
+
+    gpu.read_texture(&texture, SIZE, SIZE)
+}
+
+fn assert_partly_covered(image: &RgbaImage, (x, y): (u32, u32)) {
+    let pixel = image.get_pixel(x, y).0;
+    assert!(
+        0 < pixel[0] && pixel[0] < 255,
+        "pixel {x}, {y} isn't smoothed: {pixel:?}"
+    );
+}
+
+#[test]
+fn fills_have_smooth_edges() {
+    let gpu = require_gpu!();
+
+    // the edge crosses the middle of the pixels left and right of the center
+    let image = render_feathered(&gpu, |piet| {
+        piet.fill(Circle::new((16.0, 16.0), 10.5), &Color::BLACK)
+    });
+
+    assert_eq!(image.get_pixel(16, 16).0, [0, 0, 0, 255]);
+    assert_eq!(image.get_pixel(1, 1).0, [255; 4]);
+    assert_partly_covered(&image, (5, 16));
+    assert_partly_covered(&image, (26, 16));
+}
+
+#[test]
+fn strokes_have_smooth_edges() {
+    let gpu = require_gpu!();
+
+    // from 14.5 to 17.5, half covering the rows above and below
+    let image = render_feathered(&gpu, |piet| {
+        piet.stroke(Line::new((4.0, 16.0), (28.0, 16.0)), &Color::BLACK, 3.0)
+    });
+
+    assert_eq!(image.get_pixel(16, 15).0, [0, 0, 0, 255]);
+    assert_eq!(image.get_pixel(16, 16).0, [0, 0, 0, 255]);
+    assert_eq!(image.get_pixel(16, 12).0, [255; 4]);
+    assert_partly_covered(&image, (16, 14));
+    assert_partly_covered(&image, (16, 17));
+}
@@ -0,0 +1,436 @@
+//! Rendering piet's sample pictures headless and comparing the results.
+
+// shared by several test crates, each using only a part of it
+#![allow(dead_code, unused_macros)]
+
+#[cfg(feature = "cpu-reference")]
+pub mod reference;
+
+use std::{
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use image::{Rgba, RgbaImage};
+use piet_wgpu::{
+    immediate::WgpuImmediateRenderer, retained::WgpuRetainedRenderer, wgpu, Config, PietWgpu,
+    RenderContext, Size,
+};
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Set to skip the tests needing a software adapter on machines without one, instead of failing.
+pub const SKIP_GPU_TESTS: &str = "PIET_WGPU_SKIP_GPU_TESTS";
+
+/// Sample pictures drawing something piet-wgpu doesn't implement yet, with the first thing they
+/// hit. Every other sample has to render.
+pub const UNIMPLEMENTED_SAMPLES: &[(usize, &str)] = &[
+    (0, "text"),
+    (2, "make_image"),
+    (3, "save and restore"),
+    (5, "text"),
+    (7, "text"),
+    (8, "text"),
+    (9, "text"),
+    (10, "text"),
+    (11, "text"),
+    (12, "text"),
+    (13, "text"),
+    (14, "text"),
+    (15, "save and restore"),
+];
+
+/// The software device of the test, see `Gpu::software_with_limits`.
+///
+/// Returns from the test if there is no software adapter and `PIET_WGPU_SKIP_GPU_TESTS` is set,
+/// without the variable the test fails.
+macro_rules! require_gpu {
+    () => {
+        require_gpu!(piet_wgpu::wgpu::Limits::downlevel_webgl2_defaults())
+    };
+    ($limits:expr) => {
+        match $crate::common::Gpu::software_with_limits($limits) {
+            Some(gpu) => gpu,
+            None => {
+                $crate::common::missing_gpu();
+                return;
+            }
+        }
+    };
+}
+
+/// Fails the test unless tests without a software adapter are skipped on purpose.
+pub fn missing_gpu() {
+    assert!(
+        std::env::var_os(SKIP_GPU_TESTS).is_some(),
+        "no software adapter found, set {SKIP_GPU_TESTS}=1 to skip the tests needing one"
+    );
+    eprintln!("skipping, no software adapter found");
+}
+
+/// The first unimplemented thing sample picture `number` draws, if it's one of
+/// `UNIMPLEMENTED_SAMPLES`.
+pub fn unimplemented_feature(number: usize) -> Option<&'static str> {
+    UNIMPLEMENTED_SAMPLES
+        .iter()
+        .find(|(sample, _)| *sample == number)
+        .map(|(_, feature)| *feature)
+}
+
+/// Device of a software adapter, so results don't depend on the gpu of the machine.
+pub struct Gpu {
+    pub device: Arc<wgpu::Device>,
+    pub queue: Arc<wgpu::Queue>,
+}
+
+impl Gpu {
+    /// A software device with `limits`, to force limits lower than the adapter's. `None` if
+    /// there is no software adapter, like llvmpipe or WARP.
+    pub fn software_with_limits(limits: wgpu::Limits) -> Option<Self> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter =
+            futures::executor::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                force_fallback_adapter: true,
+                compatible_surface: None,
+            }))?;
+
+        let (device, queue) = futures::executor::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("Sample Device"),
+                features: wgpu::Features::empty(),
+                limits,
+            },
+            None,
+        ))
+        .ok()?;
+
+        Some(Self {
+            device: Arc::new(device),
+            queue: Arc::new(queue),
+        })
+    }
+
+    /// Renders sample picture `number`, `None` if it's one of `UNIMPLEMENTED_SAMPLES`.
+    ///
+    /// Those have to run into a `todo!()`, any other panic fails the test.
+    pub fn render_sample(&self, number: usize) -> Option<RgbaImage> {
+        let sample =
+            piet::samples::get::<PietWgpu<WgpuImmediateRenderer>>(number).expect("sample exists");
+        let rendered = catch_panic(|| self.render(sample.size(), |piet| sample.draw(piet)));
+
+        match (rendered, unimplemented_feature(number)) {
+            (Ok(image), None) => Some(image),
+            (Err(message), Some(_)) if message.starts_with("not yet implemented") => None,
+            (Ok(_), Some(feature)) => {
+                panic!("picture {number:02} renders, take it off UNIMPLEMENTED_SAMPLES ({feature})")
+            }
+            (Err(message), _) => panic!("picture {number:02} panicked: {message}"),
+        }
+    }
+
+    /// Renders what `draw` draws.
+    pub fn render(
+        &self,
+        size: Size,
+        draw: impl FnOnce(&mut PietWgpu<WgpuImmediateRenderer>) -> Result<(), piet::Error>,
+    ) -> RgbaImage {
+        self.render_with_format(size, FORMAT, draw)
+    }
+
+    /// Renders what `draw` draws into a texture of `format`.
+    pub fn render_with_format(
+        &self,
+        size: Size,
+        format: wgpu::TextureFormat,
+        draw: impl FnOnce(&mut PietWgpu<WgpuImmediateRenderer>) -> Result<(), piet::Error>,
+    ) -> RgbaImage {
+        let (width, height) = (size.width as u32, size.height as u32);
+        let (mut piet, texture) = self.piet_with_format(width, height, format);
+
+        draw(&mut piet)
+            .and_then(|_| piet.finish())
+            .expect("renders without errors");
+
+        self.read_texture(&texture, width, height)
+    }
+
+    /// A renderer drawing into the returned texture.
+    pub fn piet(
+        &self,
+        width: u32,
+        height: u32,
+    ) -> (PietWgpu<WgpuImmediateRenderer>, wgpu::Texture) {
+        self.piet_with_format(width, height, FORMAT)
+    }
+
+    /// A renderer drawing into the returned texture of `format`.
+    pub fn piet_with_format(
+        &self,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> (PietWgpu<WgpuImmediateRenderer>, wgpu::Texture) {
+        self.piet_with(width, height, format, Config::default())
+    }
+
+    /// A renderer configured with `config` drawing into the returned texture.
+    pub fn piet_with_config(
+        &self,
+        width: u32,
+        height: u32,
+        config: Config,
+    ) -> (PietWgpu<WgpuImmediateRenderer>, wgpu::Texture) {
+        self.piet_with(width, height, FORMAT, config)
+    }
+
+    fn piet_with(
+        &self,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        config: Config,
+    ) -> (PietWgpu<WgpuImmediateRenderer>, wgpu::Texture) {
+        let texture = self.target_texture(width, height, format);
+
+        let renderer = WgpuImmediateRenderer::from_device(
+            self.device.clone(),
+            self.queue.clone(),
+            format,
+            width,
+            height,
+            1.0,
+            config,
+        )
+        .expect("renderer for the sample device");
+
+        let mut piet = PietWgpu::from_renderer(renderer, width, height, 1.0);
+        piet.renderer
+            .set_target_view(texture.create_view(&wgpu::TextureViewDescriptor::default()));
+
+        (piet, texture)
+    }
+
+    /// A retained renderer drawing into the returned texture.
+    pub fn retained(
+        &self,
+        width: u32,
+        height: u32,
+        config: Config,
+    ) -> (PietWgpu<WgpuRetainedRenderer>, wgpu::Texture) {
+        let texture = self.target_texture(width, height, FORMAT);
+
+        let renderer = WgpuRetainedRenderer::from_device(
+            self.device.clone(),
+            self.queue.clone(),
+            FORMAT,
+            width,
+            height,
+            1.0,
+            config,
+        )
+        .expect("renderer for the sample device");
+
+        let mut piet = PietWgpu::from_renderer(renderer, width, height, 1.0);
+        piet.renderer
+            .set_target_view(texture.create_view(&wgpu::TextureViewDescriptor::default()));
+
+        (piet, texture)
+    }
+
+    fn target_texture(
+        &self,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::Texture {
+        self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Sample Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        })
+    }
+
+    pub fn read_texture(&self, texture: &wgpu::Texture, width: u32, height: u32) -> RgbaImage {
+        let padded_row = (width * 4).div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| {
+            result.expect("readback buffer maps");
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+
+        let data = slice.get_mapped_range();
+        let pixels = data
+            .chunks(padded_row as usize)
+            .flat_map(|row| &row[..(width * 4) as usize])
+            .copied()
+            .collect();
+
+        RgbaImage::from_raw(width, height, pixels).expect("readback has the texture's size")
+    }
+}
+
+/// Runs `draw`, `None` if it panics.
+pub fn catch_unimplemented<T>(draw: impl FnOnce() -> T) -> Option<T> {
+    catch_panic(draw).ok()
+}
+
+/// Runs `draw`, failing with the panic's message if it panics.
+pub fn catch_panic<T>(draw: impl FnOnce() -> T) -> Result<T, String> {
+    // unimplemented parts of a render context panic, callers report what's unexpected
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let drawn = panic::catch_unwind(AssertUnwindSafe(draw));
+    panic::set_hook(hook);
+
+    drawn.map_err(|payload| match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload.downcast_ref::<&str>().map_or_else(
+            || "unknown panic".to_string(),
+            |message| message.to_string(),
+        ),
+    })
+}
+
+/// Directory for images written by failing tests.
+pub fn output_dir() -> PathBuf {
+    output_subdir("samples")
+}
+
+/// Directory `name` for images written by the tests.
+pub fn output_subdir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::create_dir_all(&dir).expect("output directory");
+    dir
+}
+
+/// Fails unless the pixel at `x`, `y` is `expected`, give or take one for rounding.
+pub fn assert_pixel(image: &RgbaImage, (x, y): (u32, u32), expected: [u8; 4]) {
+    let actual = image.get_pixel(x, y).0;
+    let close = actual
+        .iter()
+        .zip(expected)
+        .all(|(actual, expected)| actual.abs_diff(expected) <= 1);
+
+    assert!(close, "pixel {x}, {y} is {actual:?}, expected {expected:?}");
+}
+
+/// Perceptual difference of two pixels as CIE76 delta E, after compositing them over white.
+/// Differences below about 2.3 aren't noticeable.
+pub fn delta_e(a: Rgba<u8>, b: Rgba<u8>) -> f32 {
+    let (a, b) = (lab(a), lab(b));
+
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+fn lab(pixel: Rgba<u8>) -> [f32; 3] {
+    let alpha = pixel[3] as f32 / 255.0;
+    let linear = |channel: u8| {
+        let channel = channel as f32 / 255.0;
+        let channel = match channel <= 0.04045 {
+            true => channel / 12.92,
+            false => ((channel + 0.055) / 1.055).powf(2.4),
+        };
+        channel * alpha + (1.0 - alpha)
+    };
+    let (r, g, b) = (linear(pixel[0]), linear(pixel[1]), linear(pixel[2]));
+
+    // xyz relative to the D65 white point
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.9505;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.089;
+
+    let f = |t: f32| match t > 0.008856 {
+        true => t.cbrt(),
+        false => 7.787 * t + 16.0 / 116.0,
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// Delta E of every pixel, the images have to be of the same size.
+pub fn error_map(expected: &RgbaImage, actual: &RgbaImage) -> Vec<f32> {
+    expected
+        .pixels()
+        .zip(actual.pixels())
+        .map(|(expected, actual)| delta_e(*expected, *actual))
+        .collect()
+}
+
+/// Faded `expected` with pixels differing by more than `threshold` in red, brighter the larger
+/// the difference.
+pub fn diff_image(expected: &RgbaImage, errors: &[f32], threshold: f32) -> RgbaImage {
+    RgbaImage::from_fn(expected.width(), expected.height(), |x, y| {
+        let error = errors[(y * expected.width() + x) as usize];
+
+        if error > threshold {
+            let intensity = (128.0 + error * 4.0).min(255.0) as u8;
+            return Rgba([intensity, 0, 0, 255]);
+        }
+
+        let pixel = expected.get_pixel(x, y);
+        let luma = (pixel[0] as u32 * 2 + pixel[1] as u32 * 5 + pixel[2] as u32) / 8;
+        let faded = (192 + luma / 4) as u8;
+        Rgba([faded, faded, faded, 255])
+    })
+}
+
+/// Error map with black for equal pixels, blue to red for differences up to `max` and above.
+pub fn heatmap(width: u32, height: u32, errors: &[f32], max: f32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        let error = errors[(y * width + x) as usize];
+
+        if error == 0.0 {
+            return Rgba([0, 0, 0, 255]);
+        }
+
+        let t = (error / max).min(1.0);
+        let ramp = |t: f32| (t.clamp(0.0, 1.0) * 255.0) as u8;
+        Rgba([
+            ramp(t * 2.0),
+            ramp(1.0 - (t * 2.0 - 1.0).abs()),
+            ramp(1.0 - t * 2.0),
+            255,
+        ])
+    })
+}
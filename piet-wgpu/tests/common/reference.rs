@@ -0,0 +1,442 @@
+//! A piet render context rasterizing on the cpu with tiny-skia, as reference for what the gpu
+//! output should look like.
+
+use std::borrow::Cow;
+
+use image::RgbaImage;
+use piet::{
+    kurbo::{Affine, PathEl, Point, Rect, Shape, Size},
+    Color, Error, FixedGradient, ImageFormat, InterpolationMode, IntoBrush, LineCap, LineJoin,
+    NullText, NullTextLayout, RenderContext, StrokeStyle,
+};
+use tiny_skia::{
+    ClipMask, FillRule, FilterQuality, GradientStop, LinearGradient, Paint, PathBuilder, Pattern,
+    Pixmap, RadialGradient, Shader, SpreadMode, Stroke, StrokeDash, Transform,
+};
+
+use super::catch_unimplemented;
+
+/// Renders sample picture `number` on the cpu, `None` if the reference can't draw it either.
+pub fn render_sample(number: usize) -> Option<RgbaImage> {
+    let sample = piet::samples::get::<CpuRenderContext>(number).expect("sample exists");
+    let mut context = CpuRenderContext::new(sample.size());
+
+    catch_unimplemented(|| sample.draw(&mut context).and_then(|_| context.finish()))?
+        .expect("sample renders without errors");
+
+    Some(context.into_image())
+}
+
+#[derive(Clone)]
+pub enum CpuBrush {
+    Solid(Color),
+    Gradient(FixedGradient),
+}
+
+impl IntoBrush<CpuRenderContext> for CpuBrush {
+    fn make_brush<'a>(
+        &'a self,
+        _piet: &mut CpuRenderContext,
+        _bbox: impl FnOnce() -> Rect,
+    ) -> Cow<'a, CpuBrush> {
+        Cow::Borrowed(self)
+    }
+}
+
+#[derive(Clone)]
+pub struct CpuImage(Pixmap);
+
+impl piet::Image for CpuImage {
+    fn size(&self) -> Size {
+        Size::new(self.0.width() as f64, self.0.height() as f64)
+    }
+}
+
+#[derive(Clone)]
+struct State {
+    transform: Affine,
+    clip: Option<ClipMask>,
+}
+
+pub struct CpuRenderContext {
+    pixmap: Pixmap,
+    text: NullText,
+    state: State,
+    saved: Vec<State>,
+}
+
+impl CpuRenderContext {
+    fn new(size: Size) -> Self {
+        Self {
+            pixmap: Pixmap::new(size.width as u32, size.height as u32).expect("non-empty sample"),
+            text: NullText,
+            state: State {
+                transform: Affine::IDENTITY,
+                clip: None,
+            },
+            saved: Vec::new(),
+        }
+    }
+
+    /// The rendered picture with separate alpha, like the readback of the gpu.
+    fn into_image(self) -> RgbaImage {
+        let (width, height) = (self.pixmap.width(), self.pixmap.height());
+        let pixels = self
+            .pixmap
+            .pixels()
+            .iter()
+            .flat_map(|pixel| {
+                let color = pixel.demultiply();
+                [color.red(), color.green(), color.blue(), color.alpha()]
+            })
+            .collect();
+
+        RgbaImage::from_raw(width, height, pixels).expect("pixmap has its size")
+    }
+
+    fn fill_shape(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, fill_rule: FillRule) {
+        let brush = brush.make_brush(self, || shape.bounding_box()).into_owned();
+        let Some(path) = skia_path(&shape) else {
+            return;
+        };
+
+        let paint = paint(&brush);
+        let transform = skia_transform(self.state.transform);
+        self.pixmap.fill_path(
+            &path,
+            &paint,
+            fill_rule,
+            transform,
+            self.state.clip.as_ref(),
+        );
+    }
+}
+
+impl RenderContext for CpuRenderContext {
+    type Brush = CpuBrush;
+
+    type Text = NullText;
+
+    type TextLayout = NullTextLayout;
+
+    type Image = CpuImage;
+
+    fn status(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn solid_brush(&mut self, color: Color) -> Self::Brush {
+        CpuBrush::Solid(color)
+    }
+
+    fn gradient(&mut self, gradient: impl Into<FixedGradient>) -> Result<Self::Brush, Error> {
+        Ok(CpuBrush::Gradient(gradient.into()))
+    }
+
+    fn clear(&mut self, region: impl Into<Option<Rect>>, color: Color) {
+        // clears ignore the transform and clip
+        let rect = region
+            .into()
+            .unwrap_or_else(|| Rect::new(0.0, 0.0, 1e6, 1e6))
+            .intersect(Rect::new(
+                0.0,
+                0.0,
+                self.pixmap.width() as f64,
+                self.pixmap.height() as f64,
+            ));
+        let Some(rect) = skia_rect(rect) else {
+            return;
+        };
+
+        let mut paint = paint(&CpuBrush::Solid(color));
+        paint.blend_mode = tiny_skia::BlendMode::Source;
+        self.pixmap
+            .fill_rect(rect, &paint, Transform::identity(), None);
+    }
+
+    fn stroke(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, width: f64) {
+        self.stroke_styled(shape, brush, width, &StrokeStyle::new());
+    }
+
+    fn stroke_styled(
+        &mut self,
+        shape: impl Shape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
+    ) {
+        let brush = brush.make_brush(self, || shape.bounding_box()).into_owned();
+        let Some(path) = skia_path(&shape) else {
+            return;
+        };
+
+        let (line_join, miter_limit) = match style.line_join {
+            LineJoin::Miter { limit } => (tiny_skia::LineJoin::Miter, limit as f32),
+            LineJoin::Round => (tiny_skia::LineJoin::Round, 4.0),
+            LineJoin::Bevel => (tiny_skia::LineJoin::Bevel, 4.0),
+        };
+        let line_cap = match style.line_cap {
+            LineCap::Butt => tiny_skia::LineCap::Butt,
+            LineCap::Round => tiny_skia::LineCap::Round,
+            LineCap::Square => tiny_skia::LineCap::Square,
+        };
+        let dash = match style.dash_pattern.is_empty() {
+            true => None,
+            false => StrokeDash::new(
+                style.dash_pattern.iter().map(|dash| *dash as f32).collect(),
+                style.dash_offset as f32,
+            ),
+        };
+
+        let stroke = Stroke {
+            width: width as f32,
+            miter_limit,
+            line_cap,
+            line_join,
+            dash,
+        };
+
+        let paint = paint(&brush);
+        let transform = skia_transform(self.state.transform);
+        self.pixmap
+            .stroke_path(&path, &paint, &stroke, transform, self.state.clip.as_ref());
+    }
+
+    fn fill(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        self.fill_shape(shape, brush, FillRule::Winding);
+    }
+
+    fn fill_even_odd(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        self.fill_shape(shape, brush, FillRule::EvenOdd);
+    }
+
+    fn clip(&mut self, shape: impl Shape) {
+        let Some(path) =
+            skia_path(&shape).and_then(|path| path.transform(skia_transform(self.state.transform)))
+        else {
+            return;
+        };
+
+        let (width, height) = (self.pixmap.width(), self.pixmap.height());
+        let clip = self.state.clip.get_or_insert_with(|| {
+            let mut clip = ClipMask::new();
+            let everything = PathBuilder::from_rect(
+                tiny_skia::Rect::from_xywh(0.0, 0.0, width as f32, height as f32).unwrap(),
+            );
+            clip.set_path(width, height, &everything, FillRule::Winding, false);
+            clip
+        });
+        clip.intersect_path(&path, FillRule::Winding, true);
+    }
+
+    fn text(&mut self) -> &mut Self::Text {
+        &mut self.text
+    }
+
+    fn draw_text(&mut self, _layout: &Self::TextLayout, _pos: impl Into<Point>) {
+        todo!()
+    }
+
+    fn save(&mut self) -> Result<(), Error> {
+        self.saved.push(self.state.clone());
+        Ok(())
+    }
+
+    fn restore(&mut self) -> Result<(), Error> {
+        self.state = self.saved.pop().ok_or(Error::StackUnbalance)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn transform(&mut self, transform: Affine) {
+        self.state.transform *= transform;
+    }
+
+    fn make_image(
+        &mut self,
+        width: usize,
+        height: usize,
+        buf: &[u8],
+        format: ImageFormat,
+    ) -> Result<Self::Image, Error> {
+        let mut pixmap = Pixmap::new(width as u32, height as u32).ok_or(Error::InvalidInput)?;
+
+        for (pixel, data) in pixmap
+            .pixels_mut()
+            .iter_mut()
+            .zip(buf.chunks(format.bytes_per_pixel()))
+        {
+            let color = match format {
+                ImageFormat::Grayscale => {
+                    tiny_skia::ColorU8::from_rgba(data[0], data[0], data[0], 255)
+                }
+                ImageFormat::Rgb => tiny_skia::ColorU8::from_rgba(data[0], data[1], data[2], 255),
+                ImageFormat::RgbaSeparate => {
+                    tiny_skia::ColorU8::from_rgba(data[0], data[1], data[2], data[3])
+                }
+                ImageFormat::RgbaPremul => {
+                    *pixel = tiny_skia::PremultipliedColorU8::from_rgba(
+                        data[0], data[1], data[2], data[3],
+                    )
+                    .ok_or(Error::InvalidInput)?;
+                    continue;
+                }
+                _ => return Err(Error::NotSupported),
+            };
+            *pixel = color.premultiply();
+        }
+
+        Ok(CpuImage(pixmap))
+    }
+
+    fn draw_image(
+        &mut self,
+        image: &Self::Image,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        let src_rect = Rect::from_origin_size(Point::ZERO, piet::Image::size(image));
+        self.draw_image_area(image, src_rect, dst_rect, interp);
+    }
+
+    fn draw_image_area(
+        &mut self,
+        image: &Self::Image,
+        src_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        let (src_rect, dst_rect) = (src_rect.into(), dst_rect.into());
+        let Some(rect) = skia_rect(dst_rect) else {
+            return;
+        };
+
+        // maps the source area of the image onto the destination
+        let image_transform = Affine::translate(dst_rect.origin().to_vec2())
+            * Affine::scale_non_uniform(
+                dst_rect.width() / src_rect.width(),
+                dst_rect.height() / src_rect.height(),
+            )
+            * Affine::translate(-src_rect.origin().to_vec2());
+
+        let quality = match interp {
+            InterpolationMode::NearestNeighbor => FilterQuality::Nearest,
+            InterpolationMode::Bilinear => FilterQuality::Bilinear,
+        };
+        let paint = Paint {
+            shader: Pattern::new(
+                image.0.as_ref(),
+                SpreadMode::Pad,
+                quality,
+                1.0,
+                skia_transform(image_transform),
+            ),
+            anti_alias: true,
+            ..Default::default()
+        };
+
+        let transform = skia_transform(self.state.transform);
+        self.pixmap
+            .fill_rect(rect, &paint, transform, self.state.clip.as_ref());
+    }
+
+    fn capture_image_area(&mut self, _src_rect: impl Into<Rect>) -> Result<Self::Image, Error> {
+        todo!()
+    }
+
+    fn blurred_rect(&mut self, _rect: Rect, _blur_radius: f64, _brush: &impl IntoBrush<Self>) {
+        todo!()
+    }
+
+    fn current_transform(&self) -> Affine {
+        self.state.transform
+    }
+}
+
+fn paint(brush: &CpuBrush) -> Paint<'static> {
+    let shader = match brush {
+        CpuBrush::Solid(color) => Shader::SolidColor(skia_color(color)),
+        CpuBrush::Gradient(FixedGradient::Linear(gradient)) => LinearGradient::new(
+            skia_point(gradient.start),
+            skia_point(gradient.end),
+            skia_stops(&gradient.stops),
+            SpreadMode::Pad,
+            Transform::identity(),
+        )
+        .expect("valid linear gradient"),
+        // the gradient starts at the origin and ends on the circle around the center
+        CpuBrush::Gradient(FixedGradient::Radial(gradient)) => RadialGradient::new(
+            skia_point(gradient.center + gradient.origin_offset),
+            skia_point(gradient.center),
+            gradient.radius as f32,
+            skia_stops(&gradient.stops),
+            SpreadMode::Pad,
+            Transform::identity(),
+        )
+        .expect("valid radial gradient"),
+    };
+
+    Paint {
+        shader,
+        anti_alias: true,
+        ..Default::default()
+    }
+}
+
+fn skia_color(color: &Color) -> tiny_skia::Color {
+    let (r, g, b, a) = color.as_rgba8();
+    tiny_skia::Color::from_rgba8(r, g, b, a)
+}
+
+fn skia_stops(stops: &[piet::GradientStop]) -> Vec<GradientStop> {
+    stops
+        .iter()
+        .map(|stop| GradientStop::new(stop.pos, skia_color(&stop.color)))
+        .collect()
+}
+
+fn skia_point(point: Point) -> tiny_skia::Point {
+    tiny_skia::Point::from_xy(point.x as f32, point.y as f32)
+}
+
+fn skia_rect(rect: Rect) -> Option<tiny_skia::Rect> {
+    tiny_skia::Rect::from_ltrb(
+        rect.x0 as f32,
+        rect.y0 as f32,
+        rect.x1 as f32,
+        rect.y1 as f32,
+    )
+}
+
+fn skia_transform(affine: Affine) -> Transform {
+    let [a, b, c, d, e, f] = affine.as_coeffs();
+    Transform::from_row(a as f32, b as f32, c as f32, d as f32, e as f32, f as f32)
+}
+
+fn skia_path(shape: &impl Shape) -> Option<tiny_skia::Path> {
+    let mut builder = PathBuilder::new();
+
+    for element in shape.path_elements(0.1) {
+        match element {
+            PathEl::MoveTo(p) => builder.move_to(p.x as f32, p.y as f32),
+            PathEl::LineTo(p) => builder.line_to(p.x as f32, p.y as f32),
+            PathEl::QuadTo(p1, p2) => {
+                builder.quad_to(p1.x as f32, p1.y as f32, p2.x as f32, p2.y as f32)
+            }
+            PathEl::CurveTo(p1, p2, p3) => builder.cubic_to(
+                p1.x as f32,
+                p1.y as f32,
+                p2.x as f32,
+                p2.y as f32,
+                p3.x as f32,
+                p3.y as f32,
+            ),
+            PathEl::ClosePath => builder.close(),
+        }
+    }
+
+    builder.finish()
+}
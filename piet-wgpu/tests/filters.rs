@@ -0,0 +1,135 @@
+//! Filtering the contents of layers.
+
+#[macro_use]
+mod common;
+
+use common::{assert_pixel, Gpu};
+use image::RgbaImage;
+use piet_wgpu::{
+    wgpu, BlendMode, Color, Filter, Rect, RenderContext, RenderContextExt, Size, Vec2,
+};
+
+const SIZE: Size = Size::new(16.0, 16.0);
+const RED: Color = Color::rgb8(255, 0, 0);
+
+/// A red square in the middle of a white background, drawn into a layer with `filters`.
+fn render_filtered(gpu: &Gpu, filters: &[Filter]) -> RgbaImage {
+    gpu.render(SIZE, |piet| {
+        piet.clear(None, Color::WHITE);
+        piet.push_filtered_layer(filters, 1.0, BlendMode::SourceOver, None);
+        piet.fill(Rect::new(4.0, 4.0, 12.0, 12.0), &RED);
+        piet.pop_layer()
+    })
+}
+
+#[test]
+fn layers_are_blurred() {
+    let gpu = require_gpu!();
+
+    let image = render_filtered(&gpu, &[Filter::Blur { radius: 1.0 }]);
+
+    // the middle stays red, the edges fade into the background on both sides
+    assert_pixel(&image, (8, 8), [255, 0, 0, 255]);
+    assert_pixel(&image, (0, 0), [255; 4]);
+    for (inside, outside) in [((4, 8), (3, 8)), ((8, 4), (8, 3))] {
+        let inside = image.get_pixel(inside.0, inside.1).0;
+        let outside = image.get_pixel(outside.0, outside.1).0;
+
+        assert!(
+            0 < inside[1] && inside[1] < outside[1] && outside[1] < 255,
+            "edge isn't blurred: {inside:?}, {outside:?}"
+        );
+    }
+}
+
+#[test]
+fn layers_cast_drop_shadows() {
+    let gpu = require_gpu!();
+
+    let image = render_filtered(
+        &gpu,
+        &[Filter::DropShadow {
+            offset: Vec2::new(2.0, 3.0),
+            blur_radius: 0.0,
+            color: Color::BLACK,
+        }],
+    );
+
+    // the shadow is below the square and only shows next to it
+    assert_pixel(&image, (8, 8), [255, 0, 0, 255]);
+    assert_pixel(&image, (13, 14), [0, 0, 0, 255]);
+    assert_pixel(&image, (5, 14), [255; 4]);
+    assert_pixel(&image, (13, 5), [255; 4]);
+}
+
+#[test]
+fn color_matrices_change_the_colors() {
+    let gpu = require_gpu!();
+
+    // the luminance of red in linear light is 0.2126
+    let image = render_filtered(&gpu, &[Filter::grayscale(1.0)]);
+    assert_pixel(&image, (8, 8), [127, 127, 127, 255]);
+    assert_pixel(&image, (0, 0), [255; 4]);
+
+    let image = render_filtered(&gpu, &[Filter::brightness(0.5)]);
+    assert_pixel(&image, (8, 8), [188, 0, 0, 255]);
+
+    let image = render_filtered(&gpu, &[Filter::sepia(1.0)]);
+    let sepia = image.get_pixel(8, 8).0;
+    assert!(
+        sepia[0] > sepia[1] && sepia[1] > sepia[2],
+        "sepia isn't brown: {sepia:?}"
+    );
+}
+
+#[test]
+fn filters_are_chained() {
+    let gpu = require_gpu!();
+
+    // the shadow of a grey square is cast after it turned grey, and is red again
+    let image = render_filtered(
+        &gpu,
+        &[
+            Filter::grayscale(1.0),
+            Filter::DropShadow {
+                offset: Vec2::new(2.0, 2.0),
+                blur_radius: 0.0,
+                color: RED,
+            },
+        ],
+    );
+
+    assert_pixel(&image, (8, 8), [127, 127, 127, 255]);
+    assert_pixel(&image, (13, 13), [255, 0, 0, 255]);
+}
+
+#[test]
+fn filters_work_on_linear_colors_for_every_target_format() {
+    let gpu = require_gpu!();
+
+    // the layer is opaque away from the texture's border, which the blur fades out, so
+    // compositing it there doesn't depend on the format
+    let render = |format| {
+        gpu.render_with_format(SIZE, format, |piet| {
+            piet.push_filtered_layer(
+                &[Filter::Blur { radius: 1.0 }, Filter::brightness(0.5)],
+                1.0,
+                BlendMode::SourceOver,
+                None,
+            );
+            piet.clear(None, Color::WHITE);
+            piet.fill(Rect::new(4.0, 4.0, 12.0, 12.0), &RED);
+            piet.pop_layer()
+        })
+    };
+
+    // the shader encodes colors for targets without an sRGB format
+    let srgb = render(wgpu::TextureFormat::Rgba8UnormSrgb);
+    let linear = render(wgpu::TextureFormat::Rgba8Unorm);
+
+    for (x, y, pixel) in srgb.enumerate_pixels() {
+        if (3..13).contains(&x) && (3..13).contains(&y) {
+            assert_pixel(&linear, (x, y), pixel.0);
+        }
+    }
+}
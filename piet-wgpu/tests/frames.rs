@@ -0,0 +1,94 @@
+//! Recording animations with a headless renderer.
+
+mod common;
+
+use std::time::Duration;
+
+use piet_wgpu::{
+    immediate::WgpuImmediateRenderer, wgpu, Color, Config, FrameOutput, FrameRecorder, PietWgpu,
+    Rect, RenderContext,
+};
+
+const FRAMES: usize = 4;
+
+/// A red square moving to the right by its width every frame.
+fn record(output: FrameOutput) -> bool {
+    let (width, height) = (4 * FRAMES as u32, 4);
+    let config = Config {
+        force_fallback_adapter: true,
+        limits: wgpu::Limits::downlevel_webgl2_defaults(),
+        ..Config::default()
+    };
+    let Ok(renderer) = WgpuImmediateRenderer::headless(width, height, 1.0, config) else {
+        common::missing_gpu();
+        return false;
+    };
+
+    let mut piet = PietWgpu::from_renderer(renderer, width, height, 1.0);
+    let recorder = FrameRecorder::new(FRAMES, Duration::from_millis(40), output);
+
+    recorder
+        .record(&mut piet, |piet, index| {
+            let x = index as f64 * 4.0;
+            piet.clear(None, Color::WHITE);
+            piet.clear(Rect::new(x, 0.0, x + 4.0, 4.0), Color::rgb8(255, 0, 0));
+            Ok(())
+        })
+        .unwrap();
+    true
+}
+
+#[test]
+fn frames_record_to_png_sequence() {
+    let dir = common::output_subdir("frames");
+    if !record(FrameOutput::PngSequence(dir.clone())) {
+        return;
+    }
+
+    for index in 0..FRAMES {
+        let frame = image::open(dir.join(format!("frame-{index:04}.png")))
+            .unwrap()
+            .into_rgba8();
+
+        for square in 0..FRAMES {
+            let expected = match square == index {
+                true => [255, 0, 0, 255],
+                false => [255; 4],
+            };
+            assert_eq!(
+                frame.get_pixel(square as u32 * 4 + 2, 2).0,
+                expected,
+                "frame {index}, square {square}"
+            );
+        }
+    }
+}
+
+#[cfg(feature = "apng")]
+#[test]
+fn frames_record_to_apng() {
+    let path = common::output_subdir("frames").join("animation.png");
+    if !record(FrameOutput::Apng(path.clone())) {
+        return;
+    }
+
+    let mut reader = png::Decoder::new(std::io::BufReader::new(std::fs::File::open(path).unwrap()))
+        .read_info()
+        .unwrap();
+    let control = reader.info().animation_control.expect("png is animated");
+    assert_eq!(control.num_frames, FRAMES as u32);
+
+    let mut buffer = vec![0; reader.output_buffer_size().unwrap()];
+    for index in 0..FRAMES {
+        reader.next_frame(&mut buffer).unwrap();
+        let frame = reader.info().frame_control.unwrap();
+        assert_eq!((frame.delay_num, frame.delay_den), (40, 1000));
+
+        let square = (index * 4 + 2) * 4 + 2 * 4 * 4 * FRAMES;
+        assert_eq!(
+            buffer[square..square + 4],
+            [255, 0, 0, 255],
+            "frame {index}"
+        );
+    }
+}
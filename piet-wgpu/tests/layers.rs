@@ -0,0 +1,284 @@
+//! Compositing layers with opacity and clips.
+
+#[macro_use]
+mod common;
+
+use common::{assert_pixel, Gpu};
+use image::RgbaImage;
+use piet_wgpu::{wgpu, BlendMode, Color, Rect, RenderContext, RenderContextExt, Size};
+
+const SIZE: Size = Size::new(8.0, 8.0);
+const RED: Color = Color::rgb8(255, 0, 0);
+
+/// Red layers over a white background, `layers` gives the opacity and clip of each nested layer.
+fn render_layers(gpu: &Gpu, layers: &[(f64, Option<Rect>)]) -> RgbaImage {
+    gpu.render(SIZE, |piet| {
+        piet.clear(None, Color::WHITE);
+
+        for (opacity, clip) in layers {
+            piet.push_layer(*opacity, BlendMode::SourceOver, *clip);
+        }
+        piet.fill(SIZE.to_rect(), &RED);
+        for _ in layers {
+            piet.pop_layer()?;
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn layers_are_composited_with_their_opacity() {
+    let gpu = require_gpu!();
+
+    assert_pixel(
+        &render_layers(&gpu, &[(1.0, None)]),
+        (4, 4),
+        [255, 0, 0, 255],
+    );
+    // half of the red in linear light is 188 in sRGB
+    assert_pixel(
+        &render_layers(&gpu, &[(0.5, None)]),
+        (4, 4),
+        [255, 188, 188, 255],
+    );
+    // nested opacities multiply
+    assert_pixel(
+        &render_layers(&gpu, &[(0.5, None), (0.5, None)]),
+        (4, 4),
+        [255, 225, 225, 255],
+    );
+}
+
+#[test]
+fn layers_are_clipped() {
+    let gpu = require_gpu!();
+
+    let image = render_layers(
+        &gpu,
+        &[
+            (1.0, Some(Rect::new(0.0, 0.0, 4.0, 8.0))),
+            (1.0, Some(Rect::new(2.0, 0.0, 8.0, 8.0))),
+        ],
+    );
+
+    assert_pixel(&image, (1, 4), [255; 4]);
+    assert_pixel(&image, (3, 4), [255, 0, 0, 255]);
+    assert_pixel(&image, (5, 4), [255; 4]);
+}
+
+#[test]
+fn popping_without_layer_fails() {
+    let gpu = require_gpu!();
+
+    let (mut piet, _texture) = gpu.piet(8, 8);
+    assert!(matches!(piet.pop_layer(), Err(piet::Error::StackUnbalance)));
+}
+
+#[test]
+fn layer_textures_are_reused() {
+    let gpu = require_gpu!();
+
+    let (mut piet, texture) = gpu.piet(8, 8);
+    let mut allocations = Vec::new();
+
+    for _ in 0..3 {
+        piet.renderer
+            .set_target_view(texture.create_view(&wgpu::TextureViewDescriptor::default()));
+        piet.push_layer(0.5, BlendMode::SourceOver, None);
+        piet.push_layer(0.5, BlendMode::SourceOver, None);
+        piet.fill(SIZE.to_rect(), &RED);
+        piet.pop_layer().unwrap();
+        piet.pop_layer().unwrap();
+        piet.finish().unwrap();
+
+        allocations.push(piet.renderer.gpu_allocations());
+    }
+
+    assert_eq!(
+        allocations[1], allocations[2],
+        "frames allocate: {allocations:?}"
+    );
+}
+
+#[test]
+fn sibling_layers_share_a_texture() {
+    let gpu = require_gpu!();
+
+    let (mut piet, texture) = gpu.piet(8, 8);
+    piet.push_layer(0.5, BlendMode::SourceOver, None);
+    piet.push_layer(0.5, BlendMode::SourceOver, None);
+    piet.fill(SIZE.to_rect(), &RED);
+    piet.pop_layer().unwrap();
+    piet.pop_layer().unwrap();
+    piet.finish().unwrap();
+    let allocations = piet.renderer.gpu_allocations();
+
+    // more layers than before, but nested less deeply
+    piet.renderer
+        .set_target_view(texture.create_view(&wgpu::TextureViewDescriptor::default()));
+    piet.clear(None, Color::WHITE);
+    for (x, opacity) in [(0.0, 1.0), (2.0, 0.5), (4.0, 1.0)] {
+        piet.push_layer(opacity, BlendMode::SourceOver, None);
+        piet.fill(Rect::new(x, 0.0, x + 2.0, 8.0), &RED);
+        piet.pop_layer().unwrap();
+    }
+    piet.finish().unwrap();
+
+    assert_eq!(piet.renderer.gpu_allocations(), allocations);
+    let image = gpu.read_texture(&texture, 8, 8);
+    assert_pixel(&image, (1, 4), [255, 0, 0, 255]);
+    assert_pixel(&image, (3, 4), [255, 188, 188, 255]);
+    assert_pixel(&image, (5, 4), [255, 0, 0, 255]);
+    assert_pixel(&image, (7, 4), [255; 4]);
+}
+
+#[test]
+fn layers_paint_into_a_pass_of_the_caller() {
+    let gpu = require_gpu!();
+
+    let (mut piet, texture) = gpu.piet(8, 8);
+    piet.clear(None, Color::WHITE);
+    for (x, opacity) in [(0.0, 1.0), (2.0, 0.5)] {
+        piet.push_layer(opacity, BlendMode::SourceOver, None);
+        piet.fill(Rect::new(x, 0.0, x + 2.0, 8.0), &RED);
+        piet.pop_layer().unwrap();
+    }
+
+    let mut encoder = gpu
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    piet.prepare(&gpu.device, &gpu.queue, &mut encoder).unwrap();
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        piet.paint(&mut render_pass);
+    }
+    gpu.queue.submit(Some(encoder.finish()));
+
+    let image = gpu.read_texture(&texture, 8, 8);
+    assert_pixel(&image, (1, 4), [255, 0, 0, 255]);
+    assert_pixel(&image, (3, 4), [255, 188, 188, 255]);
+    assert_pixel(&image, (5, 4), [255; 4]);
+}
+
+/// An opaque red layer blended with `blend_mode` over a white background.
+fn render_blended(gpu: &Gpu, blend_mode: BlendMode) -> RgbaImage {
+    gpu.render(SIZE, |piet| {
+        piet.clear(None, Color::WHITE);
+        piet.push_layer(1.0, blend_mode, None);
+        piet.fill(SIZE.to_rect(), &RED);
+        piet.pop_layer()
+    })
+}
+
+#[test]
+fn layers_are_blended_with_the_backdrop() {
+    let gpu = require_gpu!();
+
+    let red = [255, 0, 0, 255];
+    let white = [255; 4];
+    let transparent = [0; 4];
+    let expected = [
+        (BlendMode::Clear, transparent),
+        (BlendMode::Copy, red),
+        (BlendMode::Destination, white),
+        (BlendMode::SourceOver, red),
+        (BlendMode::DestinationOver, white),
+        (BlendMode::SourceIn, red),
+        (BlendMode::DestinationIn, white),
+        (BlendMode::SourceOut, transparent),
+        (BlendMode::DestinationOut, transparent),
+        (BlendMode::SourceAtop, red),
+        (BlendMode::DestinationAtop, white),
+        (BlendMode::Xor, transparent),
+        (BlendMode::Plus, white),
+        (BlendMode::Multiply, red),
+        (BlendMode::Screen, white),
+        (BlendMode::Overlay, white),
+        (BlendMode::Darken, red),
+        (BlendMode::Lighten, white),
+        (BlendMode::ColorDodge, white),
+        (BlendMode::Difference, [0, 255, 255, 255]),
+        // white has no hue or saturation to take
+        (BlendMode::Hue, white),
+        (BlendMode::Saturation, white),
+        (BlendMode::Color, white),
+        // grey with the luminosity of red, 0.3 in linear light
+        (BlendMode::Luminosity, [149, 149, 149, 255]),
+    ];
+
+    for (blend_mode, expected) in expected {
+        let image = render_blended(&gpu, blend_mode);
+        let actual = image.get_pixel(4, 4).0;
+        let close = actual
+            .iter()
+            .zip(expected)
+            .all(|(actual, expected)| actual.abs_diff(expected) <= 1);
+
+        assert!(
+            close,
+            "{blend_mode:?} gives {actual:?}, expected {expected:?}"
+        );
+    }
+}
+
+#[test]
+fn nested_layers_are_blended_with_their_parent() {
+    let gpu = require_gpu!();
+
+    // the inner layer only sees the outer one as backdrop, not the black target
+    let image = gpu.render(SIZE, |piet| {
+        piet.clear(None, Color::BLACK);
+        piet.push_layer(1.0, BlendMode::SourceOver, None);
+        piet.fill(SIZE.to_rect(), &Color::WHITE);
+        piet.push_layer(1.0, BlendMode::Difference, Rect::new(0.0, 0.0, 4.0, 8.0));
+        piet.fill(SIZE.to_rect(), &RED);
+        piet.pop_layer()?;
+        piet.pop_layer()
+    });
+
+    assert_pixel(&image, (1, 4), [0, 255, 255, 255]);
+    assert_pixel(&image, (5, 4), [255; 4]);
+}
+
+#[test]
+fn colors_blend_in_linear_light_for_every_target_format() {
+    let gpu = require_gpu!();
+
+    // a translucent fill, a translucent layer and a layer multiplied with the grey backdrop,
+    // each of which would come out lighter if blended in sRGB
+    let grey = Color::grey8(128);
+    let render = |format| {
+        gpu.render_with_format(SIZE, format, |piet| {
+            piet.clear(None, grey.clone());
+            piet.fill(Rect::new(0.0, 0.0, 2.0, 8.0), &RED.with_alpha(0.5));
+
+            piet.push_layer(0.5, BlendMode::SourceOver, Rect::new(2.0, 0.0, 4.0, 8.0));
+            piet.fill(SIZE.to_rect(), &RED);
+            piet.pop_layer()?;
+
+            piet.push_layer(1.0, BlendMode::Multiply, Rect::new(4.0, 0.0, 6.0, 8.0));
+            piet.fill(SIZE.to_rect(), &grey);
+            piet.pop_layer()
+        })
+    };
+
+    let srgb = render(wgpu::TextureFormat::Rgba8UnormSrgb);
+    let linear = render(wgpu::TextureFormat::Rgba8Unorm);
+
+    for (x, y, pixel) in srgb.enumerate_pixels() {
+        assert_pixel(&linear, (x, y), pixel.0);
+    }
+}
@@ -0,0 +1,50 @@
+//! Frames exceeding the limits of the device.
+
+#[macro_use]
+mod common;
+
+use piet_wgpu::{wgpu, Color, Rect, RenderContext};
+
+/// Draws `count` small rects, each with its own vertices and primitive.
+fn draw_rects(piet: &mut impl RenderContext, count: usize) {
+    for index in 0..count {
+        let x = (index % 64) as f64;
+        let y = (index / 64) as f64;
+        piet.clear(Rect::new(x, y, x + 1.0, y + 1.0), Color::WHITE);
+    }
+}
+
+#[test]
+fn buffers_larger_than_the_device_allows_fail() {
+    let gpu = require_gpu!(wgpu::Limits {
+        max_buffer_size: 64 * 1024,
+        ..wgpu::Limits::downlevel_webgl2_defaults()
+    });
+
+    let (mut piet, _texture) = gpu.piet(64, 64);
+    draw_rects(&mut piet, 4096);
+
+    let error = piet.finish().expect_err("vertices don't fit into 64KiB");
+    assert!(
+        error.to_string().contains("would need"),
+        "unexpected error: {error}"
+    );
+}
+
+#[test]
+fn primitives_past_the_storage_binding_fail() {
+    // small enough for the frame's primitives not to fit into one binding
+    let gpu = require_gpu!(wgpu::Limits {
+        max_storage_buffer_binding_size: 1024,
+        ..wgpu::Limits::downlevel_defaults()
+    });
+
+    let (mut piet, _texture) = gpu.piet(64, 64);
+    draw_rects(&mut piet, 256);
+
+    let error = piet.finish().expect_err("primitives don't fit into 1KiB");
+    assert!(
+        error.to_string().contains("primitives"),
+        "unexpected error: {error}"
+    );
+}
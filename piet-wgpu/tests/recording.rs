@@ -0,0 +1,154 @@
+//! Recording piet's sample pictures into display lists, saving them and replaying them.
+//!
+//! Run with `cargo test --features recording --test recording`.
+
+#[macro_use]
+mod common;
+
+use piet_wgpu::{
+    recording::{Command, DisplayList, Recorder, TextAttribute, TextLayoutData},
+    Color, InterpolationMode, Point, Rect, RenderContext, Size,
+};
+
+fn record_sample(number: usize) -> DisplayList {
+    let sample = piet::samples::get::<Recorder>(number).expect("sample exists");
+    let mut recorder = Recorder::new();
+
+    sample
+        .draw(&mut recorder)
+        .and_then(|_| recorder.finish())
+        .expect("sample records without errors");
+
+    recorder.take()
+}
+
+/// Compares lists command by command, the images are too large to print them all.
+fn assert_same(actual: &DisplayList, expected: &DisplayList, message: &str) {
+    assert!(actual.images == expected.images, "{message}, images differ");
+    assert_eq!(actual.commands.len(), expected.commands.len(), "{message}");
+
+    for (actual, expected) in actual.commands.iter().zip(&expected.commands) {
+        assert_eq!(actual, expected, "{message}");
+    }
+}
+
+#[test]
+fn samples_survive_saving_and_replaying() {
+    for number in 0..piet::samples::SAMPLE_COUNT {
+        let list = record_sample(number);
+        assert!(
+            !list.commands.is_empty(),
+            "picture {number:02}: nothing recorded"
+        );
+
+        let saved = serde_json::to_string(&list).unwrap();
+        let loaded: DisplayList = serde_json::from_str(&saved).unwrap();
+        assert_same(
+            &loaded,
+            &list,
+            &format!("picture {number:02}: changed by saving"),
+        );
+
+        let mut recorder = Recorder::new();
+        loaded.replay(&mut recorder).unwrap();
+        assert_same(
+            &recorder.take(),
+            &list,
+            &format!("picture {number:02}: changed by replaying"),
+        );
+    }
+}
+
+#[test]
+fn samples_export_to_svg() {
+    let dir = common::output_subdir("svg");
+
+    for number in 0..piet::samples::SAMPLE_COUNT {
+        let sample = piet::samples::get::<Recorder>(number).expect("sample exists");
+        let svg = record_sample(number).to_svg(sample.size()).unwrap();
+
+        assert!(
+            svg.starts_with("<svg"),
+            "picture {number:02}: no svg element"
+        );
+        assert!(
+            svg.trim_end().ends_with("</svg>"),
+            "picture {number:02}: unclosed"
+        );
+        std::fs::write(dir.join(format!("picture-{number:02}.svg")), svg).unwrap();
+    }
+}
+
+#[test]
+fn svg_export_keeps_characters_split_by_attributes() {
+    // byte 2 is inside the é
+    let list = DisplayList {
+        images: Vec::new(),
+        commands: vec![Command::DrawText {
+            layout: TextLayoutData {
+                text: "héllo".to_string(),
+                range_attributes: vec![(0..2, TextAttribute::Weight(700))],
+                ..Default::default()
+            },
+            position: Point::ZERO,
+        }],
+    };
+
+    let svg = list.to_svg(Size::new(64.0, 16.0)).unwrap();
+    assert!(svg.contains("hé"), "{svg}");
+    assert!(svg.contains("llo"), "{svg}");
+}
+
+#[test]
+fn images_are_stored_once() {
+    let mut recorder = Recorder::new();
+    let image = recorder
+        .make_image(2, 1, &[255; 6], piet::ImageFormat::Rgb)
+        .unwrap();
+
+    recorder.draw_image(
+        &image,
+        Rect::new(0.0, 0.0, 2.0, 1.0),
+        InterpolationMode::Bilinear,
+    );
+    recorder.draw_image(
+        &image.clone(),
+        Rect::new(2.0, 0.0, 4.0, 1.0),
+        InterpolationMode::Bilinear,
+    );
+
+    let list = recorder.take();
+    assert_eq!(list.images.len(), 1);
+    assert!(matches!(
+        list.commands[..],
+        [
+            Command::DrawImage { image: 0, .. },
+            Command::DrawImage { image: 0, .. }
+        ]
+    ));
+}
+
+#[test]
+fn restore_without_save_fails() {
+    let mut recorder = Recorder::new();
+    recorder.clear(None, Color::WHITE);
+
+    assert!(recorder.restore().is_err());
+}
+
+#[test]
+fn replaying_matches_drawing() {
+    let gpu = require_gpu!();
+
+    for number in 0..piet::samples::SAMPLE_COUNT {
+        let Some(drawn) = gpu.render_sample(number) else {
+            continue;
+        };
+
+        let list = record_sample(number);
+        let size = Size::new(drawn.width() as f64, drawn.height() as f64);
+        let replayed = gpu.render(size, |piet| list.replay(piet));
+
+        assert!(drawn == replayed, "picture {number:02}: replay differs");
+    }
+}
@@ -0,0 +1,86 @@
+//! Cross-backend comparison, every sample picture is rendered by piet-wgpu and by tiny-skia on
+//! the cpu, and the share of pixels differing noticeably has to stay below a threshold. Pictures
+//! using features piet-wgpu doesn't implement yet are skipped, see `UNIMPLEMENTED_SAMPLES`.
+//!
+//! Run with `cargo test --features cpu-reference --test reference`. Error maps of all compared
+//! pictures are written to `target/tmp/reference`, black where both renderers agree and blue to
+//! red the larger the difference.
+
+#[macro_use]
+mod common;
+
+use common::{error_map, heatmap, output_subdir, reference};
+
+/// Pixels differing by less than a just noticeable difference are considered equal.
+const DELTA_E_THRESHOLD: f32 = 2.3;
+
+/// Difference shown as full red in the error maps.
+const HEATMAP_MAX: f32 = 50.0;
+
+/// Share of pixels allowed to differ from the cpu rendering in picture `number`.
+fn differing_pixels(number: usize) -> f32 {
+    match number {
+        // gradient brushes fill with the color of their first stop
+        4 | 6 => 0.07,
+        // anti-aliasing of edges differs between the rasterizers
+        _ => 0.01,
+    }
+}
+
+#[test]
+fn samples_match_cpu_reference() {
+    let gpu = require_gpu!();
+
+    let dir = output_subdir("reference");
+    let mut failures = Vec::new();
+
+    for number in 0..piet::samples::SAMPLE_COUNT {
+        let Some(actual) = gpu.render_sample(number) else {
+            let feature = common::unimplemented_feature(number).unwrap_or_default();
+            eprintln!("picture {number:02}: skipped, piet-wgpu doesn't implement {feature}");
+            continue;
+        };
+        let Some(expected) = reference::render_sample(number) else {
+            eprintln!(
+                "picture {number:02}: skipped, uses features the reference doesn't implement"
+            );
+            continue;
+        };
+
+        let errors = error_map(&expected, &actual);
+        let differing = errors
+            .iter()
+            .filter(|error| **error > DELTA_E_THRESHOLD)
+            .count() as f32
+            / errors.len() as f32;
+        let max = errors.iter().copied().fold(0.0, f32::max);
+        let mean = errors.iter().sum::<f32>() / errors.len() as f32;
+
+        let map_path = dir.join(format!("picture-{number:02}-errors.png"));
+        heatmap(expected.width(), expected.height(), &errors, HEATMAP_MAX)
+            .save(&map_path)
+            .unwrap();
+        expected
+            .save(dir.join(format!("picture-{number:02}-cpu.png")))
+            .unwrap();
+        actual
+            .save(dir.join(format!("picture-{number:02}-gpu.png")))
+            .unwrap();
+
+        eprintln!(
+            "picture {number:02}: max delta E {max:.1}, mean {mean:.2}, {:.2}% differing",
+            differing * 100.0
+        );
+
+        if differing > differing_pixels(number) {
+            failures.push(format!(
+                "picture {number:02}: {:.2}% of pixels differ, at most {:.2}% allowed, see {}",
+                differing * 100.0,
+                differing_pixels(number) * 100.0,
+                map_path.display()
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
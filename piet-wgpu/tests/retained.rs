@@ -0,0 +1,78 @@
+//! Keeping nodes between frames with the retained renderer.
+
+#[macro_use]
+mod common;
+
+use image::{Rgba, RgbaImage};
+use piet_wgpu::{Color, Config, InterpolationMode, Rect, RenderContext, Vec2, WgpuImage};
+
+fn square(color: [u8; 4]) -> WgpuImage {
+    WgpuImage::from_rgba(RgbaImage::from_pixel(8, 8, Rgba(color)))
+}
+
+#[test]
+fn removed_nodes_free_their_images_in_the_atlas() {
+    let gpu = require_gpu!();
+
+    // room for two of the squares
+    let config = Config {
+        texture_buffer_dimensions: Vec2::new(16.0, 8.0),
+        ..Config::default()
+    };
+    let (mut piet, texture) = gpu.retained(24, 8, config);
+
+    let squares = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+    for (id, color) in squares.into_iter().enumerate() {
+        let image = square(color);
+        let rect = Rect::new(id as f64 * 8.0, 0.0, id as f64 * 8.0 + 8.0, 8.0);
+
+        // the view is used up by every frame, nodes are drawn over a transparent background
+        piet.renderer
+            .set_target_view(texture.create_view(&Default::default()));
+        piet.clear(None, Color::TRANSPARENT);
+        piet.node(id as u64, |piet| {
+            piet.draw_image(&image, rect, InterpolationMode::NearestNeighbor)
+        });
+        piet.finish().expect("the square fits into the atlas");
+
+        // the first square makes room for the third
+        if id == 1 {
+            assert!(piet.remove_node(0));
+        }
+    }
+
+    let image = gpu.read_texture(&texture, 24, 8);
+    assert_eq!(image.get_pixel(4, 4).0, [0, 0, 0, 0], "removed square");
+    assert_eq!(image.get_pixel(12, 4).0, squares[1], "square kept");
+    assert_eq!(image.get_pixel(20, 4).0, squares[2], "square added");
+}
+
+#[test]
+fn unchanged_nodes_keep_their_draw_calls() {
+    let gpu = require_gpu!();
+
+    let (mut piet, texture) = gpu.retained(16, 8, Config::default());
+    let colors = [Color::rgb8(255, 0, 0), Color::rgb8(0, 0, 255)];
+
+    for (frame, color) in colors.iter().enumerate() {
+        piet.renderer
+            .set_target_view(texture.create_view(&Default::default()));
+        piet.clear(None, Color::TRANSPARENT);
+        // only the first frame draws the left node, the right one changes its color
+        if frame == 0 {
+            piet.node(0, |piet| {
+                piet.clear(Rect::new(0.0, 0.0, 8.0, 8.0), Color::WHITE)
+            });
+        }
+        piet.node(1, |piet| {
+            piet.clear(Rect::new(8.0, 0.0, 16.0, 8.0), color.clone())
+        });
+        piet.finish().unwrap();
+
+        assert_eq!(piet.renderer.uploaded_nodes(), 2 - frame);
+    }
+
+    let image = gpu.read_texture(&texture, 16, 8);
+    assert_eq!(image.get_pixel(4, 4).0, [255; 4], "node kept");
+    assert_eq!(image.get_pixel(12, 4).0, [0, 0, 255, 255], "node redrawn");
+}
@@ -0,0 +1,90 @@
+//! Golden image tests, every sample picture of piet is compared with the reference in
+//! `tests/reference`.
+//!
+//! Run with `PIET_WGPU_BLESS=1` to write the current output as new references after an
+//! intended change. Images of failing samples are written to `target/tmp/samples`.
+
+#[macro_use]
+mod common;
+
+use std::path::PathBuf;
+
+use common::{diff_image, error_map, output_dir};
+
+/// Pixels differing by less than a just noticeable difference are considered equal.
+const DELTA_E_THRESHOLD: f32 = 2.3;
+
+/// Share of pixels allowed to differ, for edge pixels rasterized differently by other drivers.
+const DIFFERING_PIXELS: f32 = 0.002;
+
+fn reference_path(number: usize) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("reference")
+        .join(format!("picture-{number:02}.png"))
+}
+
+#[test]
+fn samples_match_references() {
+    let gpu = require_gpu!();
+
+    let bless = std::env::var_os("PIET_WGPU_BLESS").is_some();
+    let mut failures = Vec::new();
+
+    for number in 0..piet::samples::SAMPLE_COUNT {
+        let Some(actual) = gpu.render_sample(number) else {
+            let feature = common::unimplemented_feature(number).unwrap_or_default();
+            eprintln!("picture {number:02}: skipped, {feature} isn't implemented");
+            continue;
+        };
+
+        let reference = reference_path(number);
+
+        if bless {
+            std::fs::create_dir_all(reference.parent().unwrap()).unwrap();
+            actual.save(&reference).unwrap();
+            continue;
+        }
+
+        let expected = match image::open(&reference) {
+            Ok(expected) => expected.into_rgba8(),
+            Err(error) => {
+                failures.push(format!("picture {number:02}: no reference ({error})"));
+                continue;
+            }
+        };
+
+        if expected.dimensions() != actual.dimensions() {
+            failures.push(format!(
+                "picture {number:02}: size {:?} instead of {:?}",
+                actual.dimensions(),
+                expected.dimensions()
+            ));
+            continue;
+        }
+
+        let errors = error_map(&expected, &actual);
+        let differing = errors
+            .iter()
+            .filter(|error| **error > DELTA_E_THRESHOLD)
+            .count();
+
+        if differing as f32 > errors.len() as f32 * DIFFERING_PIXELS {
+            let dir = output_dir();
+            let actual_path = dir.join(format!("picture-{number:02}-actual.png"));
+            let diff_path = dir.join(format!("picture-{number:02}-diff.png"));
+
+            actual.save(&actual_path).unwrap();
+            diff_image(&expected, &errors, DELTA_E_THRESHOLD)
+                .save(&diff_path)
+                .unwrap();
+
+            failures.push(format!(
+                "picture {number:02}: {differing} pixels differ, see {}",
+                diff_path.display()
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -0,0 +1,51 @@
+//! Screenshots of headless renderers.
+
+#[macro_use]
+mod common;
+
+use piet_wgpu::{encode_image, Color, Rect, RenderContext};
+
+#[test]
+fn screenshots_match_the_target() {
+    let gpu = require_gpu!();
+
+    for number in 0..piet::samples::SAMPLE_COUNT {
+        if common::unimplemented_feature(number).is_some() {
+            continue;
+        }
+        let sample = piet::samples::get(number).expect("sample exists");
+        let size = sample.size();
+        let (width, height) = (size.width as u32, size.height as u32);
+        let (mut piet, texture) = gpu.piet(width, height);
+
+        sample
+            .draw(&mut piet)
+            .and_then(|_| piet.finish())
+            .expect("sample renders without errors");
+
+        let screenshot = piet.screenshot().unwrap();
+        assert!(
+            screenshot == gpu.read_texture(&texture, width, height),
+            "picture {number:02}: screenshot differs from the target"
+        );
+    }
+}
+
+#[test]
+fn screenshots_wait_for_the_frame() {
+    let gpu = require_gpu!();
+
+    let (mut piet, _texture) = gpu.piet(4, 4);
+    piet.clear(Rect::new(0.0, 0.0, 2.0, 4.0), Color::rgb8(255, 0, 0));
+    assert!(piet.screenshot().is_err());
+
+    piet.finish().unwrap();
+    let screenshot = piet.screenshot().unwrap();
+    assert_eq!(screenshot.get_pixel(0, 0).0, [255, 0, 0, 255]);
+
+    for format in [image::ImageFormat::Png, image::ImageFormat::Jpeg] {
+        let encoded = encode_image(&screenshot, format).unwrap();
+        let decoded = image::load_from_memory_with_format(&encoded, format).unwrap();
+        assert_eq!(decoded.width(), 4, "{format:?}");
+    }
+}